@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use png_secret::Chunk;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(chunk) = Chunk::try_from(data) {
+        let _chunk_string = chunk.to_string();
+        assert_eq!(chunk.as_bytes(), &data[..chunk.as_bytes().len()]);
+    }
+});
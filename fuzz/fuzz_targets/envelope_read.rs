@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use png_secret::Envelope;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(envelope) = Envelope::read(data) {
+        assert_eq!(Envelope::read(&envelope.as_bytes().unwrap()).unwrap(), envelope);
+    }
+});
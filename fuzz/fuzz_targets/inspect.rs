@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use png_secret::inspect::inspect;
+use png_secret::Png;
+
+fuzz_target!(|data: &[u8]| {
+    // start with the signature, every input reaches the chunks
+    let bytes = [&Png::STANDARD_HEADER[..], data].concat();
+    let inspection = inspect(&bytes).unwrap();
+    let _ = inspection.validate();
+});
@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use png_secret::Png;
+
+fuzz_target!(|data: &[u8]| {
+    // parsing must return an error on bad input, never panic
+    if let Ok(png) = Png::try_from(data) {
+        // a parsed png is written back byte for byte
+        assert_eq!(png.as_bytes(), data);
+    }
+});
@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use png_secret::zlib;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(decompressed) = zlib::decompress(data, 1 << 20) {
+        assert_eq!(zlib::decompress(&zlib::compress(&decompressed, 6), 1 << 20).unwrap(), decompressed);
+    }
+});
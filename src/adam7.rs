@@ -0,0 +1,146 @@
+//! Adam7 interlacing: the pixels are sent in seven passes, each one a
+//! smaller image taken from a grid over the full image.
+
+use crate::ihdr::Ihdr;
+use crate::image::{bits_per_pixel, row_bytes};
+
+// (first column, first row, column step, row step) of each pass
+const PASSES: [(u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// Width and height of each pass, some can be empty for small images.
+pub(crate) fn pass_sizes(width: u32, height: u32) -> [(u32, u32); 7] {
+    PASSES.map(|(x, y, dx, dy)| {
+        let size = |length: u32, start: u32, step: u32| length.saturating_sub(start).div_ceil(step);
+        (size(width, x, dx), size(height, y, dy))
+    })
+}
+
+/// Put the pixels of the seven `passes` back into full image rows.
+pub(crate) fn deinterlace(ihdr: &Ihdr, passes: &[Vec<u8>]) -> Vec<u8> {
+    let bits = bits_per_pixel(ihdr);
+    let stride = row_bytes(ihdr.width(), bits);
+    let mut data = vec![0; stride * ihdr.height() as usize];
+
+    for_each_pixel(ihdr, |pass, pass_index, index| {
+        copy_pixel(&passes[pass], pass_index, &mut data, index, bits);
+    });
+    data
+}
+
+/// Split full image rows into the seven passes.
+pub(crate) fn interlace(ihdr: &Ihdr, data: &[u8]) -> Vec<Vec<u8>> {
+    let bits = bits_per_pixel(ihdr);
+    let mut passes: Vec<Vec<u8>> = pass_sizes(ihdr.width(), ihdr.height())
+        .iter()
+        .map(|(width, height)| vec![0; row_bytes(*width, bits) * *height as usize])
+        .collect();
+
+    for_each_pixel(ihdr, |pass, pass_index, index| {
+        copy_pixel(data, index, &mut passes[pass], pass_index, bits);
+    });
+    passes
+}
+
+// call `f` with (pass, bit index in the pass, bit index in the image) for
+// every pixel
+fn for_each_pixel(ihdr: &Ihdr, mut f: impl FnMut(usize, usize, usize)) {
+    let bits = bits_per_pixel(ihdr);
+    let stride = row_bytes(ihdr.width(), bits);
+    let sizes = pass_sizes(ihdr.width(), ihdr.height());
+
+    for (pass, (x0, y0, dx, dy)) in PASSES.iter().enumerate() {
+        let (width, height) = sizes[pass];
+        let pass_stride = row_bytes(width, bits);
+
+        for py in 0..height as usize {
+            let y = *y0 as usize + py * *dy as usize;
+            for px in 0..width as usize {
+                let x = *x0 as usize + px * *dx as usize;
+                f(pass, py * pass_stride * 8 + px * bits, y * stride * 8 + x * bits);
+            }
+        }
+    }
+}
+
+// copy `bits` bits from bit `from` of `source` to bit `to` of `destination`
+fn copy_pixel(source: &[u8], from: usize, destination: &mut [u8], to: usize, bits: usize) {
+    if bits >= 8 {
+        let length = bits / 8;
+        destination[to / 8..to / 8 + length].copy_from_slice(&source[from / 8..from / 8 + length]);
+    } else {
+        let mask = ((1u16 << bits) - 1) as u8;
+        let value = (source[from / 8] >> (8 - bits - from % 8)) & mask;
+        let shift = 8 - bits - to % 8;
+        destination[to / 8] = (destination[to / 8] & !(mask << shift)) | (value << shift);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::{ColorType, InterlaceMethod};
+
+    fn ihdr(width: u32, height: u32, bit_depth: u8, color_type: ColorType) -> Ihdr {
+        Ihdr::new(width, height, bit_depth, color_type, InterlaceMethod::Adam7).unwrap()
+    }
+
+    #[test]
+    fn test_pass_sizes() {
+        assert_eq!(
+            pass_sizes(8, 8),
+            [(1, 1), (1, 1), (2, 1), (2, 2), (4, 2), (4, 4), (8, 4)]
+        );
+        assert_eq!(
+            pass_sizes(1, 1),
+            [(1, 1), (0, 1), (1, 0), (0, 1), (1, 0), (0, 1), (1, 0)]
+        );
+    }
+
+    #[test]
+    fn test_interlace_8x8() {
+        let ihdr = ihdr(8, 8, 8, ColorType::Grayscale);
+        let data: Vec<u8> = (0..64).collect();
+
+        let passes = interlace(&ihdr, &data);
+        assert_eq!(passes[0], [0]);
+        assert_eq!(passes[1], [4]);
+        assert_eq!(passes[2], [32, 36]);
+        assert_eq!(passes[6], (8..16).chain(24..32).chain(40..48).chain(56..64).collect::<Vec<u8>>());
+
+        assert_eq!(deinterlace(&ihdr, &passes), data);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for (bit_depth, color_type) in [
+            (1, ColorType::Grayscale),
+            (2, ColorType::Indexed),
+            (4, ColorType::Grayscale),
+            (8, ColorType::Truecolor),
+            (16, ColorType::TruecolorAlpha),
+        ] {
+            for (width, height) in [(1, 1), (3, 5), (13, 9), (17, 2)] {
+                let ihdr = ihdr(width, height, bit_depth, color_type);
+                let bits = bits_per_pixel(&ihdr);
+                let stride = row_bytes(width, bits);
+
+                // keep the row padding bits at 0
+                let mut data: Vec<u8> = (0..stride * height as usize).map(|i| (i * 37 + 11) as u8).collect();
+                let padding = stride * 8 - width as usize * bits;
+                for row in data.chunks_mut(stride) {
+                    row[stride - 1] &= !((1u16 << padding) - 1) as u8;
+                }
+
+                assert_eq!(deinterlace(&ihdr, &interlace(&ihdr, &data)), data);
+            }
+        }
+    }
+}
@@ -0,0 +1,725 @@
+//! Typed data of the standard ancillary chunks.
+//!
+//! Every type is read from a [`Chunk`] with `TryFrom<&Chunk>` and turned back
+//! into one with `From`/`Into<Chunk>`.
+
+use std::str::FromStr;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::ColorType;
+use crate::zlib;
+use crate::{PngSecretError, Result};
+
+/// `gAMA` : image gamma, times 100000.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gama {
+    pub gamma: u32,
+}
+
+impl Gama {
+    pub fn value(&self) -> f64 {
+        self.gamma as f64 / 100000.0
+    }
+}
+
+impl TryFrom<&Chunk> for Gama {
+    type Error = PngSecretError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let data = check(chunk, "gAMA", Some(4))?;
+        Ok(Gama { gamma: be_u32(data) })
+    }
+}
+
+impl From<Gama> for Chunk {
+    fn from(value: Gama) -> Self {
+        new_chunk("gAMA", value.gamma.to_be_bytes().to_vec())
+    }
+}
+
+/// `cHRM` : chromaticities of the white point and primaries, times 100000.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chrm {
+    pub white_x: u32,
+    pub white_y: u32,
+    pub red_x: u32,
+    pub red_y: u32,
+    pub green_x: u32,
+    pub green_y: u32,
+    pub blue_x: u32,
+    pub blue_y: u32,
+}
+
+impl TryFrom<&Chunk> for Chrm {
+    type Error = PngSecretError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let data = check(chunk, "cHRM", Some(32))?;
+        let value = |index: usize| be_u32(&data[index * 4..]);
+
+        Ok(Chrm {
+            white_x: value(0),
+            white_y: value(1),
+            red_x: value(2),
+            red_y: value(3),
+            green_x: value(4),
+            green_y: value(5),
+            blue_x: value(6),
+            blue_y: value(7),
+        })
+    }
+}
+
+impl From<Chrm> for Chunk {
+    fn from(value: Chrm) -> Self {
+        let data = [
+            value.white_x,
+            value.white_y,
+            value.red_x,
+            value.red_y,
+            value.green_x,
+            value.green_y,
+            value.blue_x,
+            value.blue_y,
+        ]
+        .iter()
+        .flat_map(|v| v.to_be_bytes())
+        .collect();
+        new_chunk("cHRM", data)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderingIntent {
+    Perceptual,
+    RelativeColorimetric,
+    Saturation,
+    AbsoluteColorimetric,
+}
+
+/// `sRGB` : the image uses the sRGB color space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Srgb {
+    pub intent: RenderingIntent,
+}
+
+impl TryFrom<&Chunk> for Srgb {
+    type Error = PngSecretError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let data = check(chunk, "sRGB", Some(1))?;
+        let intent = match data[0] {
+            0 => RenderingIntent::Perceptual,
+            1 => RenderingIntent::RelativeColorimetric,
+            2 => RenderingIntent::Saturation,
+            3 => RenderingIntent::AbsoluteColorimetric,
+            intent => return Err(invalid("sRGB", format!("unknown rendering intent {}", intent))),
+        };
+        Ok(Srgb { intent })
+    }
+}
+
+impl From<Srgb> for Chunk {
+    fn from(value: Srgb) -> Self {
+        let intent = match value.intent {
+            RenderingIntent::Perceptual => 0,
+            RenderingIntent::RelativeColorimetric => 1,
+            RenderingIntent::Saturation => 2,
+            RenderingIntent::AbsoluteColorimetric => 3,
+        };
+        new_chunk("sRGB", vec![intent])
+    }
+}
+
+/// `iCCP` : embedded ICC profile, still compressed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Iccp {
+    pub profile_name: String,
+    pub compression_method: u8,
+    pub compressed_profile: Vec<u8>,
+}
+
+impl Iccp {
+    /// Decompress the ICC profile.
+    pub fn profile(&self) -> Result<Vec<u8>> {
+        if self.compression_method != 0 {
+            return Err(invalid("iCCP", format!("unknown compression method {}", self.compression_method)));
+        }
+        zlib::decompress(&self.compressed_profile, zlib::DEFAULT_LIMIT)
+    }
+}
+
+impl TryFrom<&Chunk> for Iccp {
+    type Error = PngSecretError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let data = check(chunk, "iCCP", None)?;
+        let (profile_name, rest) = read_keyword("iCCP", data)?;
+        let (compression_method, compressed_profile) = rest
+            .split_first()
+            .ok_or_else(|| invalid("iCCP", "missing compression method".to_string()))?;
+
+        Ok(Iccp {
+            profile_name,
+            compression_method: *compression_method,
+            compressed_profile: compressed_profile.to_vec(),
+        })
+    }
+}
+
+impl From<Iccp> for Chunk {
+    fn from(value: Iccp) -> Self {
+        let mut data = write_keyword(&value.profile_name);
+        data.push(value.compression_method);
+        data.extend(value.compressed_profile);
+        new_chunk("iCCP", data)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhysUnit {
+    Unknown,
+    Meter,
+}
+
+/// `pHYs` : pixels per unit, or aspect ratio with an unknown unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Phys {
+    pub x: u32,
+    pub y: u32,
+    pub unit: PhysUnit,
+}
+
+impl TryFrom<&Chunk> for Phys {
+    type Error = PngSecretError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let data = check(chunk, "pHYs", Some(9))?;
+        let unit = match data[8] {
+            0 => PhysUnit::Unknown,
+            1 => PhysUnit::Meter,
+            unit => return Err(invalid("pHYs", format!("unknown unit {}", unit))),
+        };
+        Ok(Phys {
+            x: be_u32(data),
+            y: be_u32(&data[4..]),
+            unit,
+        })
+    }
+}
+
+impl From<Phys> for Chunk {
+    fn from(value: Phys) -> Self {
+        let mut data = Vec::with_capacity(9);
+        data.extend(value.x.to_be_bytes());
+        data.extend(value.y.to_be_bytes());
+        data.push(match value.unit {
+            PhysUnit::Unknown => 0,
+            PhysUnit::Meter => 1,
+        });
+        new_chunk("pHYs", data)
+    }
+}
+
+/// `sBIT` : significant bits of each channel, in the channel order of the
+/// color type (gray or red, green, blue, then alpha).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sbit {
+    pub bits: Vec<u8>,
+}
+
+impl TryFrom<&Chunk> for Sbit {
+    type Error = PngSecretError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let data = check(chunk, "sBIT", None)?;
+        if data.is_empty() || data.len() > 4 {
+            return Err(invalid("sBIT", format!("data must be 1 to 4 bytes, found {}", data.len())));
+        }
+        Ok(Sbit { bits: data.to_vec() })
+    }
+}
+
+impl From<Sbit> for Chunk {
+    fn from(value: Sbit) -> Self {
+        new_chunk("sBIT", value.bits)
+    }
+}
+
+/// `bKGD` : default background color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bkgd {
+    Palette(u8),
+    Gray(u16),
+    Rgb(u16, u16, u16),
+}
+
+impl TryFrom<&Chunk> for Bkgd {
+    type Error = PngSecretError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let data = check(chunk, "bKGD", None)?;
+        match data.len() {
+            1 => Ok(Bkgd::Palette(data[0])),
+            2 => Ok(Bkgd::Gray(be_u16(data))),
+            6 => Ok(Bkgd::Rgb(be_u16(data), be_u16(&data[2..]), be_u16(&data[4..]))),
+            length => Err(invalid("bKGD", format!("data must be 1, 2 or 6 bytes, found {}", length))),
+        }
+    }
+}
+
+impl From<Bkgd> for Chunk {
+    fn from(value: Bkgd) -> Self {
+        let data = match value {
+            Bkgd::Palette(index) => vec![index],
+            Bkgd::Gray(gray) => gray.to_be_bytes().to_vec(),
+            Bkgd::Rgb(r, g, b) => [r, g, b].iter().flat_map(|v| v.to_be_bytes()).collect(),
+        };
+        new_chunk("bKGD", data)
+    }
+}
+
+/// `hIST` : usage frequency of each palette entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hist {
+    pub frequencies: Vec<u16>,
+}
+
+impl TryFrom<&Chunk> for Hist {
+    type Error = PngSecretError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let data = check(chunk, "hIST", None)?;
+        if data.len() % 2 != 0 {
+            return Err(invalid("hIST", format!("odd data length {}", data.len())));
+        }
+        Ok(Hist {
+            frequencies: data.chunks_exact(2).map(be_u16).collect(),
+        })
+    }
+}
+
+impl From<Hist> for Chunk {
+    fn from(value: Hist) -> Self {
+        new_chunk("hIST", value.frequencies.iter().flat_map(|v| v.to_be_bytes()).collect())
+    }
+}
+
+/// `tRNS` : transparent color, or alpha of each palette entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trns {
+    Gray(u16),
+    Rgb(u16, u16, u16),
+    Palette(Vec<u8>),
+}
+
+impl Trns {
+    /// Read a `tRNS` chunk knowing the color type of the image.
+    ///
+    /// `TryFrom<&Chunk>` guesses the color type from the data length, which
+    /// is ambiguous for palettes of 2 or 6 entries.
+    pub fn from_chunk(chunk: &Chunk, color_type: ColorType) -> Result<Trns> {
+        let data = check(chunk, "tRNS", None)?;
+        match (color_type, data.len()) {
+            (ColorType::Grayscale, 2) => Ok(Trns::Gray(be_u16(data))),
+            (ColorType::Truecolor, 6) => Ok(Trns::Rgb(be_u16(data), be_u16(&data[2..]), be_u16(&data[4..]))),
+            (ColorType::Indexed, length) if length <= 256 => Ok(Trns::Palette(data.to_vec())),
+            (color_type, length) => Err(invalid(
+                "tRNS",
+                format!("{} bytes not allowed for {} images", length, color_type),
+            )),
+        }
+    }
+}
+
+impl TryFrom<&Chunk> for Trns {
+    type Error = PngSecretError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let color_type = match chunk.data().len() {
+            2 => ColorType::Grayscale,
+            6 => ColorType::Truecolor,
+            _ => ColorType::Indexed,
+        };
+        Trns::from_chunk(chunk, color_type)
+    }
+}
+
+impl From<Trns> for Chunk {
+    fn from(value: Trns) -> Self {
+        let data = match value {
+            Trns::Gray(gray) => gray.to_be_bytes().to_vec(),
+            Trns::Rgb(r, g, b) => [r, g, b].iter().flat_map(|v| v.to_be_bytes()).collect(),
+            Trns::Palette(alphas) => alphas,
+        };
+        new_chunk("tRNS", data)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpltEntry {
+    pub red: u16,
+    pub green: u16,
+    pub blue: u16,
+    pub alpha: u16,
+    pub frequency: u16,
+}
+
+/// `sPLT` : suggested palette. Samples are 8 or 16 bits, `sample_depth`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Splt {
+    pub name: String,
+    pub sample_depth: u8,
+    pub entries: Vec<SpltEntry>,
+}
+
+impl TryFrom<&Chunk> for Splt {
+    type Error = PngSecretError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let data = check(chunk, "sPLT", None)?;
+        let (name, rest) = read_keyword("sPLT", data)?;
+        let (sample_depth, entries) = rest
+            .split_first()
+            .ok_or_else(|| invalid("sPLT", "missing sample depth".to_string()))?;
+
+        let entry_length = match sample_depth {
+            8 => 6,
+            16 => 10,
+            depth => return Err(invalid("sPLT", format!("invalid sample depth {}", depth))),
+        };
+        if entries.len() % entry_length != 0 {
+            return Err(invalid("sPLT", "truncated palette entry".to_string()));
+        }
+
+        let entries = entries
+            .chunks_exact(entry_length)
+            .map(|entry| {
+                // 8 bit samples and 16 bit frequency, or all 16 bit
+                let sample = |index: usize| match sample_depth {
+                    8 => entry[index] as u16,
+                    _ => be_u16(&entry[index * 2..]),
+                };
+                SpltEntry {
+                    red: sample(0),
+                    green: sample(1),
+                    blue: sample(2),
+                    alpha: sample(3),
+                    frequency: be_u16(&entry[entry_length - 2..]),
+                }
+            })
+            .collect();
+
+        Ok(Splt {
+            name,
+            sample_depth: *sample_depth,
+            entries,
+        })
+    }
+}
+
+impl From<Splt> for Chunk {
+    fn from(value: Splt) -> Self {
+        let mut data = write_keyword(&value.name);
+        data.push(value.sample_depth);
+        for entry in value.entries {
+            for sample in [entry.red, entry.green, entry.blue, entry.alpha] {
+                match value.sample_depth {
+                    8 => data.push(sample as u8),
+                    _ => data.extend(sample.to_be_bytes()),
+                }
+            }
+            data.extend(entry.frequency.to_be_bytes());
+        }
+        new_chunk("sPLT", data)
+    }
+}
+
+/// `tIME` : last modification time, in UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Time {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl TryFrom<&Chunk> for Time {
+    type Error = PngSecretError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let data = check(chunk, "tIME", Some(7))?;
+        let time = Time {
+            year: be_u16(data),
+            month: data[2],
+            day: data[3],
+            hour: data[4],
+            minute: data[5],
+            // 60 for leap seconds
+            second: data[6],
+        };
+
+        if !(1..=12).contains(&time.month)
+            || !(1..=31).contains(&time.day)
+            || time.hour > 23
+            || time.minute > 59
+            || time.second > 60
+        {
+            return Err(invalid("tIME", "date out of range".to_string()));
+        }
+
+        Ok(time)
+    }
+}
+
+impl From<Time> for Chunk {
+    fn from(value: Time) -> Self {
+        let mut data = value.year.to_be_bytes().to_vec();
+        data.extend([value.month, value.day, value.hour, value.minute, value.second]);
+        new_chunk("tIME", data)
+    }
+}
+
+// check the chunk type, and the data length if it's fixed
+fn check<'a>(chunk: &'a Chunk, chunk_type: &str, length: Option<usize>) -> Result<&'a [u8]> {
+    if chunk.chunk_type().to_string() != chunk_type {
+        return Err(invalid(chunk_type, format!("expected {}, found {}", chunk_type, chunk.chunk_type())));
+    }
+
+    let data = chunk.data();
+    match length {
+        Some(length) if data.len() != length => Err(invalid(
+            chunk_type,
+            format!("data must be {} bytes, found {}", length, data.len()),
+        )),
+        _ => Ok(data),
+    }
+}
+
+fn invalid(chunk_type: &str, reason: String) -> PngSecretError {
+    PngSecretError::InvalidChunkData {
+        chunk_type: chunk_type.to_string(),
+        reason,
+    }
+}
+
+// used only for the standard chunk types above
+fn new_chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+    Chunk::new(ChunkType::from_str(chunk_type).expect("valid chunk type"), data)
+}
+
+fn be_u16(bytes: &[u8]) -> u16 {
+    u16::from_be_bytes([bytes[0], bytes[1]])
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Read a null terminated Latin-1 keyword of 1 to 79 bytes, and return it with
+/// the bytes following the null separator.
+pub(crate) fn read_keyword<'a>(chunk_type: &str, data: &'a [u8]) -> Result<(String, &'a [u8])> {
+    let end = data
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or_else(|| invalid(chunk_type, "missing null separator".to_string()))?;
+
+    if end == 0 || end > 79 {
+        return Err(invalid(chunk_type, format!("keyword must be 1 to 79 bytes, found {}", end)));
+    }
+
+    Ok((latin1_to_string(&data[..end]), &data[end + 1..]))
+}
+
+/// Write `keyword` in Latin-1 followed by a null separator.
+pub(crate) fn write_keyword(keyword: &str) -> Vec<u8> {
+    let mut data = string_to_latin1(keyword);
+    data.push(0);
+    data
+}
+
+pub(crate) fn latin1_to_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| *b as char).collect()
+}
+
+// characters outside Latin-1 are replaced with '?'
+pub(crate) fn string_to_latin1(string: &str) -> Vec<u8> {
+    string
+        .chars()
+        .map(|c| u8::try_from(c as u32).unwrap_or(b'?'))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_gama() {
+        let gama = Gama::try_from(&chunk("gAMA", &[0, 0, 177, 143])).unwrap();
+        assert_eq!(gama.gamma, 45455);
+        assert!((gama.value() - 0.45455).abs() < 1e-9);
+        assert_eq!(Chunk::from(gama), chunk("gAMA", &[0, 0, 177, 143]));
+
+        assert!(Gama::try_from(&chunk("gAMA", &[0, 0, 177])).is_err());
+        assert!(Gama::try_from(&chunk("sRGB", &[0, 0, 177, 143])).is_err());
+    }
+
+    #[test]
+    fn test_chrm() {
+        let data: Vec<u8> = (1..=8u32).flat_map(|v| v.to_be_bytes()).collect();
+        let chrm = Chrm::try_from(&chunk("cHRM", &data)).unwrap();
+        assert_eq!(chrm.white_x, 1);
+        assert_eq!(chrm.red_y, 4);
+        assert_eq!(chrm.blue_y, 8);
+        assert_eq!(Chunk::from(chrm), chunk("cHRM", &data));
+    }
+
+    #[test]
+    fn test_srgb() {
+        let srgb = Srgb::try_from(&chunk("sRGB", &[0])).unwrap();
+        assert_eq!(srgb.intent, RenderingIntent::Perceptual);
+        assert_eq!(Chunk::from(srgb), chunk("sRGB", &[0]));
+
+        assert!(Srgb::try_from(&chunk("sRGB", &[4])).is_err());
+    }
+
+    #[test]
+    fn test_iccp() {
+        let iccp = Iccp::try_from(&chunk("iCCP", b"Profile\0\0\x78\x9c")).unwrap();
+        assert_eq!(iccp.profile_name, "Profile");
+        assert_eq!(iccp.compression_method, 0);
+        assert_eq!(iccp.compressed_profile, vec![0x78, 0x9c]);
+        assert_eq!(Chunk::from(iccp), chunk("iCCP", b"Profile\0\0\x78\x9c"));
+
+        assert!(Iccp::try_from(&chunk("iCCP", b"Profile")).is_err());
+        assert!(Iccp::try_from(&chunk("iCCP", b"Profile\0")).is_err());
+        assert!(Iccp::try_from(&chunk("iCCP", b"\0\0")).is_err());
+    }
+
+    #[test]
+    fn test_iccp_profile() {
+        let iccp = Iccp {
+            profile_name: "Profile".to_string(),
+            compression_method: 0,
+            compressed_profile: zlib::compress(b"profile data", 6),
+        };
+        assert_eq!(iccp.profile().unwrap(), b"profile data");
+
+        let iccp = Iccp::try_from(&chunk("iCCP", b"Profile\0\0\x78\x9c")).unwrap();
+        assert!(iccp.profile().is_err());
+    }
+
+    #[test]
+    fn test_phys() {
+        let data = [0, 0, 14, 194, 0, 0, 14, 194, 1];
+        let phys = Phys::try_from(&chunk("pHYs", &data)).unwrap();
+        assert_eq!(phys, Phys { x: 3778, y: 3778, unit: PhysUnit::Meter });
+        assert_eq!(Chunk::from(phys), chunk("pHYs", &data));
+
+        assert!(Phys::try_from(&chunk("pHYs", &[0, 0, 14, 194, 0, 0, 14, 194, 2])).is_err());
+    }
+
+    #[test]
+    fn test_sbit() {
+        let sbit = Sbit::try_from(&chunk("sBIT", &[5, 6, 5])).unwrap();
+        assert_eq!(sbit.bits, vec![5, 6, 5]);
+        assert_eq!(Chunk::from(sbit), chunk("sBIT", &[5, 6, 5]));
+
+        assert!(Sbit::try_from(&chunk("sBIT", &[])).is_err());
+        assert!(Sbit::try_from(&chunk("sBIT", &[8, 8, 8, 8, 8])).is_err());
+    }
+
+    #[test]
+    fn test_bkgd() {
+        assert_eq!(Bkgd::try_from(&chunk("bKGD", &[3])).unwrap(), Bkgd::Palette(3));
+        assert_eq!(Bkgd::try_from(&chunk("bKGD", &[1, 0])).unwrap(), Bkgd::Gray(256));
+
+        let data = [0, 1, 0, 2, 0, 3];
+        let bkgd = Bkgd::try_from(&chunk("bKGD", &data)).unwrap();
+        assert_eq!(bkgd, Bkgd::Rgb(1, 2, 3));
+        assert_eq!(Chunk::from(bkgd), chunk("bKGD", &data));
+
+        assert!(Bkgd::try_from(&chunk("bKGD", &[0, 1, 0])).is_err());
+    }
+
+    #[test]
+    fn test_hist() {
+        let hist = Hist::try_from(&chunk("hIST", &[0, 1, 1, 0])).unwrap();
+        assert_eq!(hist.frequencies, vec![1, 256]);
+        assert_eq!(Chunk::from(hist), chunk("hIST", &[0, 1, 1, 0]));
+
+        assert!(Hist::try_from(&chunk("hIST", &[0, 1, 1])).is_err());
+    }
+
+    #[test]
+    fn test_trns() {
+        assert_eq!(Trns::try_from(&chunk("tRNS", &[0, 7])).unwrap(), Trns::Gray(7));
+        assert_eq!(Trns::try_from(&chunk("tRNS", &[0, 1, 0, 2, 0, 3])).unwrap(), Trns::Rgb(1, 2, 3));
+        assert_eq!(Trns::try_from(&chunk("tRNS", &[255, 0, 128])).unwrap(), Trns::Palette(vec![255, 0, 128]));
+
+        // 2 palette entries look like a gray value
+        let trns = Trns::from_chunk(&chunk("tRNS", &[255, 0]), ColorType::Indexed).unwrap();
+        assert_eq!(trns, Trns::Palette(vec![255, 0]));
+        assert_eq!(Chunk::from(trns), chunk("tRNS", &[255, 0]));
+
+        assert!(Trns::from_chunk(&chunk("tRNS", &[255, 0]), ColorType::TruecolorAlpha).is_err());
+        assert!(Trns::from_chunk(&chunk("tRNS", &[255, 0, 0]), ColorType::Truecolor).is_err());
+    }
+
+    #[test]
+    fn test_splt() {
+        let data = b"Palette\0\x08\x01\x02\x03\x04\x00\x05";
+        let splt = Splt::try_from(&chunk("sPLT", data)).unwrap();
+        assert_eq!(splt.name, "Palette");
+        assert_eq!(splt.sample_depth, 8);
+        assert_eq!(
+            splt.entries,
+            vec![SpltEntry { red: 1, green: 2, blue: 3, alpha: 4, frequency: 5 }]
+        );
+        assert_eq!(Chunk::from(splt), chunk("sPLT", data));
+
+        let data = b"Palette\0\x10\x00\x01\x00\x02\x00\x03\x01\x00\x00\x05";
+        let splt = Splt::try_from(&chunk("sPLT", data)).unwrap();
+        assert_eq!(
+            splt.entries,
+            vec![SpltEntry { red: 1, green: 2, blue: 3, alpha: 256, frequency: 5 }]
+        );
+        assert_eq!(Chunk::from(splt), chunk("sPLT", data));
+
+        assert!(Splt::try_from(&chunk("sPLT", b"Palette\0\x08\x01\x02")).is_err());
+        assert!(Splt::try_from(&chunk("sPLT", b"Palette\0\x04")).is_err());
+    }
+
+    #[test]
+    fn test_time() {
+        let data = [7, 232, 10, 17, 22, 50, 12];
+        let time = Time::try_from(&chunk("tIME", &data)).unwrap();
+        assert_eq!(
+            time,
+            Time { year: 2024, month: 10, day: 17, hour: 22, minute: 50, second: 12 }
+        );
+        assert_eq!(Chunk::from(time), chunk("tIME", &data));
+
+        assert!(Time::try_from(&chunk("tIME", &[7, 232, 13, 17, 22, 50, 12])).is_err());
+        assert!(Time::try_from(&chunk("tIME", &[7, 232, 10, 17, 24, 50, 12])).is_err());
+    }
+
+    #[test]
+    fn test_keyword() {
+        let (keyword, rest) = read_keyword("tEXt", b"Caf\xe9\0rest").unwrap();
+        assert_eq!(keyword, "Café");
+        assert_eq!(rest, b"rest");
+        assert_eq!(write_keyword("Café"), b"Caf\xe9\0");
+
+        let long = [b'a'; 80];
+        assert!(read_keyword("tEXt", &[&long[..], b"\0"].concat()).is_err());
+    }
+}
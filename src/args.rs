@@ -1,6 +1,5 @@
 use clap::Parser;
 
-use crate::Result;
 use crate::commands::Commands;
 
 #[derive(Parser)]
@@ -0,0 +1,228 @@
+//! Files hidden as secrets, with the metadata needed to restore them.
+//!
+//! An attachment is the payload of an envelope with the
+//! [`ContentType::File`](crate::envelope::ContentType::File) content type :
+//!
+//! | bytes | content                          |
+//! |-------|----------------------------------|
+//! | 2     | length of the file name, u16 BE  |
+//! | ..    | file name, UTF-8                 |
+//! | 1     | length of the MIME type          |
+//! | ..    | MIME type, ASCII                 |
+//! | 8     | size of the file, u64 BE         |
+//! | ..    | content of the file              |
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::{PngSecretError, Result};
+
+// MIME types by file extension, for the files most likely to be hidden
+const MIME_TYPES: &[(&str, &str)] = &[
+    ("txt", "text/plain"),
+    ("md", "text/markdown"),
+    ("csv", "text/csv"),
+    ("html", "text/html"),
+    ("json", "application/json"),
+    ("toml", "application/toml"),
+    ("yaml", "application/yaml"),
+    ("yml", "application/yaml"),
+    ("xml", "application/xml"),
+    ("pem", "application/x-pem-file"),
+    ("key", "application/x-pem-file"),
+    ("crt", "application/x-x509-ca-cert"),
+    ("cer", "application/x-x509-ca-cert"),
+    ("der", "application/x-x509-ca-cert"),
+    ("p12", "application/x-pkcs12"),
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tar", "application/x-tar"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+];
+
+const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+/// A file and its metadata.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    name: String,
+    mime_type: String,
+    content: Vec<u8>,
+}
+
+impl Attachment {
+    /// Build an attachment, its MIME type is guessed from the extension of
+    /// `name`.
+    ///
+    /// `name` must be a plain file name, without directories.
+    pub fn new(name: &str, content: Vec<u8>) -> Result<Attachment> {
+        check_name(name)?;
+        Ok(Attachment {
+            name: name.to_string(),
+            mime_type: mime_type(name).to_string(),
+            content,
+        })
+    }
+
+    /// Read the file at `path`.
+    pub fn from_path(path: &Path) -> Result<Attachment> {
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| invalid(format!("'{}' has no UTF-8 file name", path.display())))?;
+        Attachment::new(name, std::fs::read(path)?)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn mime_type(&self) -> &str {
+        &self.mime_type
+    }
+
+    pub fn size(&self) -> u64 {
+        self.content.len() as u64
+    }
+
+    pub fn content(&self) -> &[u8] {
+        &self.content
+    }
+
+    /// Write the file in `directory` under its name and return its path.
+    ///
+    /// The directory is created if needed, an existing file is never overwritten.
+    pub fn save_in(&self, directory: &Path) -> Result<PathBuf> {
+        fs::create_dir_all(directory)?;
+        let path = directory.join(&self.name);
+        File::create_new(&path)?.write_all(&self.content)?;
+        Ok(path)
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(11 + self.name.len() + self.mime_type.len() + self.content.len());
+        bytes.extend_from_slice(&(self.name.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(self.name.as_bytes());
+        bytes.push(self.mime_type.len() as u8);
+        bytes.extend_from_slice(self.mime_type.as_bytes());
+        bytes.extend_from_slice(&self.size().to_be_bytes());
+        bytes.extend_from_slice(&self.content);
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for Attachment {
+    type Error = PngSecretError;
+
+    fn try_from(data: &[u8]) -> Result<Self> {
+        let mut rest = data;
+        let mut take = |length: usize| -> Result<&[u8]> {
+            if rest.len() < length {
+                return Err(invalid("truncated file metadata".to_string()));
+            }
+            let (taken, remaining) = rest.split_at(length);
+            rest = remaining;
+            Ok(taken)
+        };
+
+        let name_length = u16::from_be_bytes(take(2)?.try_into().unwrap()) as usize;
+        let name = String::from_utf8(take(name_length)?.to_vec())?;
+        let mime_length = take(1)?[0] as usize;
+        let mime_type = String::from_utf8(take(mime_length)?.to_vec())?;
+        let size = u64::from_be_bytes(take(8)?.try_into().unwrap());
+
+        check_name(&name)?;
+        if rest.len() as u64 != size {
+            return Err(invalid(format!("the file is {} bytes long, not {}", rest.len(), size)));
+        }
+
+        Ok(Attachment {
+            name,
+            mime_type,
+            content: rest.to_vec(),
+        })
+    }
+}
+
+// the name is joined to the output directory, it can't go anywhere else
+fn check_name(name: &str) -> Result<()> {
+    let is_plain = Path::new(name).file_name().and_then(|file_name| file_name.to_str()) == Some(name);
+    if !is_plain || name.contains(['/', '\\']) || name.len() > u16::MAX as usize {
+        return Err(invalid(format!("'{}' isn't a plain file name", name)));
+    }
+    Ok(())
+}
+
+fn mime_type(name: &str) -> &'static str {
+    let extension = Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    MIME_TYPES
+        .iter()
+        .find(|(known, _)| *known == extension)
+        .map_or(DEFAULT_MIME_TYPE, |(_, mime_type)| mime_type)
+}
+
+fn invalid(reason: String) -> PngSecretError {
+    PngSecretError::InvalidEnvelope(reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mime_type() {
+        assert_eq!(mime_type("config.json"), "application/json");
+        assert_eq!(mime_type("server.CRT"), "application/x-x509-ca-cert");
+        assert_eq!(mime_type("archive.tar.gz"), "application/gzip");
+        assert_eq!(mime_type("Makefile"), "application/octet-stream");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let attachment = Attachment::new("cert.pem", vec![0, 1, 2, 0xff]).unwrap();
+        assert_eq!(attachment.mime_type(), "application/x-pem-file");
+        assert_eq!(attachment.size(), 4);
+
+        let bytes = attachment.as_bytes();
+        assert_eq!(&bytes[..10], b"\x00\x08cert.pem");
+        assert_eq!(Attachment::try_from(bytes.as_slice()).unwrap(), attachment);
+    }
+
+    #[test]
+    fn test_invalid() {
+        for name in ["", "..", "../secret", "dir/file", "dir\\file", "/etc/passwd"] {
+            assert!(Attachment::new(name, vec![]).is_err(), "{}", name);
+        }
+
+        let bytes = Attachment::new("a.txt", b"abc".to_vec()).unwrap().as_bytes();
+        assert!(Attachment::try_from(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Attachment::try_from(&bytes[..5]).is_err());
+
+        // a name hiding a path
+        let mut bytes = bytes.clone();
+        bytes[2..7].copy_from_slice(b"../ab");
+        assert!(Attachment::try_from(bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_save_in() {
+        let directory = std::env::temp_dir().join(format!("png-secret-attachment-{}", std::process::id()));
+
+        let attachment = Attachment::new("restored.bin", vec![0, 159, 146, 150]).unwrap();
+        let path = attachment.save_in(&directory).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), attachment.content());
+        assert!(attachment.save_in(&directory).is_err());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,3 +1,5 @@
+#![allow(dead_code, clippy::format_in_format_args, clippy::clone_on_copy)]
+
 use std::io::Read;
 
 fn for_chunk_type() {
@@ -1,12 +1,12 @@
 #![allow(unused_variables)]
 
-use std::fmt::{write, Display};
-use std::io::Read;
+use std::fmt::Display;
 
 use crc::Crc;
 
-use crate::{Error, Result};
-use crate::chunk_type::{self, ChunkType};
+use crate::{PngSecretError, Result};
+use crate::chunk_ref::ChunkRef;
+use crate::chunk_type::ChunkType;
 
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -36,18 +36,21 @@ impl Chunk {
     }
 
     pub fn crc(&self) -> u32 {
-        const ALGO: Crc<u32> = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
-        let mut bytes: Vec<u8> = Vec::new();
-        bytes.extend(self.chunk_type.bytes().iter());
-        bytes.extend(self.data.iter());
-        ALGO.checksum(bytes.as_ref())
+        checksum(&self.chunk_type.bytes(), &self.data)
     }
 
     pub fn data_as_string(&self) -> Result<String> {
-        match String::from_utf8(self.data.clone()) {
-            Ok(string) => Ok(string),
-            Err(e) => Err(e.to_string().into())
-        }
+        Ok(String::from_utf8(self.data.clone())?)
+    }
+
+    // build a chunk read from a file, checking its CRC and chunk type
+    pub(crate) fn from_parts(chunk_type_data: [u8; 4], data: Vec<u8>, crc: u32) -> Result<Chunk> {
+        let chunk_type = checked_chunk_type(chunk_type_data, &data, crc)?;
+
+        Ok(Chunk {
+            chunk_type,
+            data
+        })
     }
 
     pub fn as_bytes(&self) -> Vec<u8> {
@@ -61,57 +64,41 @@ impl Chunk {
 }
 
 impl TryFrom<&[u8]> for Chunk {
-    type Error = Error;
+    type Error = PngSecretError;
     fn try_from(value: &[u8]) -> Result<Self> {
-        let mut value: &[u8] = value;
-
-        let mut length_data: [u8; 4] = [0; 4];
-        let mut chunk_type_data: [u8; 4] = [0; 4];
-        let mut crc_data: [u8; 4] = [0; 4];
-        let mut data: Vec<u8> = Vec::new();
-
-        // read length and chunk type data
-        value.read_exact(&mut length_data)?;
-        value.read_exact(&mut chunk_type_data)?;
-
-        // convert length data to u32 value
-        let length = u32::from_be_bytes(length_data);
-
-        // get chunk data with the length value
-        let mut iter = value.iter();
-        let mut index = 0;
-        while index < length {
-            data.push(iter.next().unwrap().clone());
-            index += 1;
-        }
-
-        // get new value without data to get CRC
-        let mut new_value: &[u8] = iter.as_ref();
-        new_value.read_exact(&mut crc_data).unwrap();
-        let crc = u32::from_be_bytes(crc_data);
-
-        // check CRC
-        const ALGO: Crc<u32> = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
-        let mut bytes = Vec::new();
-        bytes.extend(chunk_type_data.iter());
-        bytes.extend(data.iter());
-        if ALGO.checksum(bytes.as_ref()) != crc {
-            return Err("CRC not valid".into());
-        }
-
-        // try to create chunk type
-        let chunk_type = ChunkType::try_from(chunk_type_data)?;
+        ChunkRef::try_from(value).map(Chunk::from)
+    }
+}
 
-        Ok(Chunk {
-            chunk_type,
-            data
-        })
+// the CRC-32 of the PNG spec, also used for secret payloads
+pub(crate) const CRC_32: Crc<u32> = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+// the chunk type of a chunk read from a file, once its CRC is checked, so
+// the slice and the streaming parsers fail the same way
+pub(crate) fn checked_chunk_type(chunk_type_data: [u8; 4], data: &[u8], crc: u32) -> Result<ChunkType> {
+    let expected = checksum(&chunk_type_data, data);
+    if expected != crc {
+        return Err(PngSecretError::CrcMismatch {
+            chunk_type: ChunkType::try_from(chunk_type_data)?,
+            expected,
+            found: crc,
+            offset: 0,
+        });
     }
+    ChunkType::try_from(chunk_type_data)
+}
+
+// CRC of a chunk, computed on its chunk type and data
+pub(crate) fn checksum(chunk_type: &[u8; 4], data: &[u8]) -> u32 {
+    let mut digest = CRC_32.digest();
+    digest.update(chunk_type);
+    digest.update(data);
+    digest.finalize()
 }
 
 impl Display for Chunk {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}, {}, {}, {}", self.length(), self.chunk_type, self.data_as_string().unwrap(), self.crc())
+        write!(f, "{}, {}, {}, {}", self.length(), self.chunk_type, String::from_utf8_lossy(&self.data), self.crc())
     }
 }
 
@@ -219,7 +206,41 @@ mod tests {
 
         let chunk = Chunk::try_from(chunk_data.as_ref());
 
-        assert!(chunk.is_err());
+        assert!(matches!(
+            chunk,
+            Err(PngSecretError::CrcMismatch { expected: 2882656334, found: 2882656333, .. })
+        ));
+    }
+
+    #[test]
+    fn test_truncated_chunk_from_bytes() {
+        let chunk_data = testing_chunk().as_bytes();
+
+        for length in 0..chunk_data.len() {
+            let chunk = Chunk::try_from(&chunk_data[..length]);
+            assert!(matches!(chunk, Err(PngSecretError::TruncatedChunk { offset: 0 })));
+        }
+    }
+
+    #[test]
+    fn test_chunk_length_too_big() {
+        #[rustfmt::skip]
+        let chunk_data = [
+            255, 255, 255, 255, // length
+            82, 117, 83, 116,   // chunk type
+            1, 2, 3, 4,         // CRC
+        ];
+
+        let chunk = Chunk::try_from(chunk_data.as_ref());
+
+        assert!(matches!(chunk, Err(PngSecretError::TruncatedChunk { .. })));
+    }
+
+    #[test]
+    fn test_non_utf8_chunk_display() {
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), vec![0xff, 0xfe]);
+        assert!(chunk.data_as_string().is_err());
+        let _chunk_string = format!("{}", chunk);
     }
 
     #[test]
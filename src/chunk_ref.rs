@@ -0,0 +1,210 @@
+use std::fmt::Display;
+
+use crate::chunk::{self, Chunk};
+use crate::chunk_type::ChunkType;
+use crate::{PngSecretError, Result};
+
+/// A chunk borrowed from the bytes it was read from.
+///
+/// Same accessors as [`Chunk`] but nothing is copied, use
+/// [`ChunkRef::to_chunk`] to get an owned chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkRef<'a> {
+    chunk_type: ChunkType,
+    // the whole chunk: length, chunk type, data and CRC
+    bytes: &'a [u8],
+}
+
+impl<'a> ChunkRef<'a> {
+    pub fn length(&self) -> u32 {
+        self.data().len() as u32
+    }
+
+    pub fn chunk_type(&self) -> &ChunkType {
+        &self.chunk_type
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        &self.bytes[8..self.bytes.len() - 4]
+    }
+
+    pub fn crc(&self) -> u32 {
+        let crc = &self.bytes[self.bytes.len() - 4..];
+        u32::from_be_bytes([crc[0], crc[1], crc[2], crc[3]])
+    }
+
+    pub fn data_as_string(&self) -> Result<String> {
+        Ok(String::from_utf8(self.data().to_vec())?)
+    }
+
+    /// The raw bytes of the chunk, as found in the file.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk::new(self.chunk_type.clone(), self.data().to_vec())
+    }
+
+    // the chunk at the start of `bytes`, already checked by `try_from`, so
+    // neither the length nor the CRC are checked again
+    pub(crate) fn from_checked(bytes: &'a [u8]) -> ChunkRef<'a> {
+        let length = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+        let chunk_type_data = [bytes[4], bytes[5], bytes[6], bytes[7]];
+
+        ChunkRef {
+            chunk_type: ChunkType::try_from(chunk_type_data).expect("the chunk type was checked"),
+            bytes: &bytes[..length + 12],
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for ChunkRef<'a> {
+    type Error = PngSecretError;
+
+    fn try_from(value: &'a [u8]) -> Result<Self> {
+        // length [4 bytes] + chunk type [4 bytes]
+        if value.len() < 8 {
+            return Err(PngSecretError::TruncatedChunk { offset: 0 });
+        }
+        let length = u32::from_be_bytes([value[0], value[1], value[2], value[3]]) as usize;
+        let chunk_type_data = [value[4], value[5], value[6], value[7]];
+
+        // + data [length bytes] + crc [4 bytes], the length comes from the
+        // input so check it before slicing
+        let chunk_length = length
+            .checked_add(12)
+            .filter(|chunk_length| *chunk_length <= value.len())
+            .ok_or(PngSecretError::TruncatedChunk { offset: 0 })?;
+
+        let bytes = &value[..chunk_length];
+        let crc = u32::from_be_bytes(bytes[chunk_length - 4..].try_into().unwrap());
+        let chunk_type = chunk::checked_chunk_type(chunk_type_data, &bytes[8..chunk_length - 4], crc)?;
+
+        Ok(ChunkRef { chunk_type, bytes })
+    }
+}
+
+impl From<ChunkRef<'_>> for Chunk {
+    fn from(value: ChunkRef<'_>) -> Self {
+        value.to_chunk()
+    }
+}
+
+impl Display for ChunkRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}, {}, {}, {}", self.length(), self.chunk_type, String::from_utf8_lossy(self.data()), self.crc())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::png::Png;
+    use crate::reader::ChunkReader;
+
+    fn testing_chunk() -> Chunk {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        Chunk::new(chunk_type, "This is where your secret message will be!".as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_chunk_ref_from_bytes() {
+        let chunk_data = testing_chunk().as_bytes();
+        let chunk = ChunkRef::try_from(chunk_data.as_ref()).unwrap();
+
+        assert_eq!(chunk.length(), 42);
+        assert_eq!(chunk.chunk_type().to_string(), "RuSt");
+        assert_eq!(chunk.data_as_string().unwrap(), "This is where your secret message will be!");
+        assert_eq!(chunk.crc(), 2882656334);
+        assert_eq!(chunk.as_bytes(), chunk_data.as_slice());
+    }
+
+    #[test]
+    fn test_chunk_ref_borrows_data() {
+        let chunk_data = testing_chunk().as_bytes();
+        let chunk = ChunkRef::try_from(chunk_data.as_ref()).unwrap();
+
+        assert_eq!(chunk.data().as_ptr(), chunk_data[8..].as_ptr());
+    }
+
+    #[test]
+    fn test_chunk_ref_ignores_following_bytes() {
+        let mut chunk_data = testing_chunk().as_bytes();
+        let chunk_length = chunk_data.len();
+        chunk_data.extend([1, 2, 3]);
+
+        let chunk = ChunkRef::try_from(chunk_data.as_ref()).unwrap();
+
+        assert_eq!(chunk.as_bytes().len(), chunk_length);
+    }
+
+    #[test]
+    fn test_to_chunk() {
+        let chunk_data = testing_chunk().as_bytes();
+        let chunk = ChunkRef::try_from(chunk_data.as_ref()).unwrap();
+
+        assert_eq!(chunk.to_chunk(), testing_chunk());
+        assert_eq!(Chunk::from(chunk), testing_chunk());
+    }
+
+    #[test]
+    fn test_invalid_crc() {
+        let mut chunk_data = testing_chunk().as_bytes();
+        let last = chunk_data.len() - 1;
+        chunk_data[last] ^= 0xff;
+
+        let chunk = ChunkRef::try_from(chunk_data.as_ref());
+
+        assert!(matches!(chunk, Err(PngSecretError::CrcMismatch { .. })));
+    }
+
+    #[test]
+    fn test_same_errors_as_chunk_reader() {
+        let bytes = testing_chunk().as_bytes();
+        let crc = bytes.len() - 1;
+        // (index, new value) of the corrupted bytes
+        let corruptions: [&[(usize, u8)]; 4] = [
+            &[(crc, bytes[crc] ^ 0xff)],
+            &[(10, b'?')],
+            &[(5, b'5')],
+            &[(5, b'5'), (crc, bytes[crc] ^ 0xff)],
+        ];
+
+        for corruption in corruptions {
+            let mut corrupted = bytes.clone();
+            for (index, value) in corruption {
+                corrupted[*index] = *value;
+            }
+            let from_slice = ChunkRef::try_from(corrupted.as_slice()).unwrap_err();
+
+            let png = [Png::STANDARD_HEADER.as_slice(), &corrupted].concat();
+            let from_stream = ChunkReader::new(png.as_slice()).unwrap().next().unwrap().unwrap_err();
+
+            assert_eq!(std::mem::discriminant(&from_slice), std::mem::discriminant(&from_stream), "{:?}", corruption);
+        }
+    }
+
+    #[test]
+    fn test_from_checked() {
+        let mut chunk_data = testing_chunk().as_bytes();
+        let chunk_length = chunk_data.len();
+        chunk_data.extend([1, 2, 3]);
+        assert_eq!(ChunkRef::from_checked(&chunk_data), ChunkRef::try_from(chunk_data.as_ref()).unwrap());
+
+        // the CRC isn't computed again
+        chunk_data[chunk_length - 1] ^= 0xff;
+        assert_eq!(ChunkRef::from_checked(&chunk_data).crc(), 2882656334 ^ 0xff);
+    }
+
+    #[test]
+    fn test_truncated_chunk() {
+        let chunk_data = testing_chunk().as_bytes();
+
+        for length in 0..chunk_data.len() {
+            let chunk = ChunkRef::try_from(&chunk_data[..length]);
+            assert!(matches!(chunk, Err(PngSecretError::TruncatedChunk { offset: 0 })));
+        }
+    }
+}
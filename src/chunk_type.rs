@@ -2,7 +2,7 @@
 
 use std::{fmt::Display, str::FromStr};
 
-use crate::Error;
+use crate::PngSecretError;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ChunkType {
@@ -53,13 +53,28 @@ impl ChunkType {
     pub fn is_safe_to_copy(&self) -> bool {
         self.safe_to_copy.is_ascii_lowercase()
     }
+
+    /// `true` for the chunk types defined by the PNG spec.
+    pub fn is_standard(&self) -> bool {
+        ChunkType::STANDARD.contains(&&self.bytes())
+    }
+
+    const STANDARD: [&'static [u8; 4]; 18] = [
+        b"IHDR", b"PLTE", b"IDAT", b"IEND", b"cHRM", b"gAMA", b"iCCP", b"sBIT", b"sRGB",
+        b"bKGD", b"hIST", b"tRNS", b"pHYs", b"sPLT", b"tIME", b"tEXt", b"zTXt", b"iTXt",
+    ];
 }
 
 // [u8; 4] to ChunkType
 impl TryFrom<[u8; 4]> for ChunkType {
-    type Error = Error;
+    type Error = PngSecretError;
 
     fn try_from(value: [u8; 4]) -> Result<ChunkType, Self::Error> {
+        if !value.iter().all(u8::is_ascii_alphabetic) {
+            return Err(PngSecretError::InvalidChunkType(
+                String::from_utf8_lossy(&value).to_string(),
+            ));
+        }
         Ok(ChunkType {
             ancillary: value[0],
             private: value[1],
@@ -70,7 +85,7 @@ impl TryFrom<[u8; 4]> for ChunkType {
 }
 
 impl FromStr for ChunkType {
-    type Err = Error;
+    type Err = PngSecretError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         if s.len() == 4 {
@@ -79,7 +94,7 @@ impl FromStr for ChunkType {
                 if char.is_ascii_alphabetic() {
                     res.push(char as u8);
                 } else {
-                    return Err("Can't be a number".into());
+                    return Err(PngSecretError::InvalidChunkType(s.to_string()));
                 }
             }
             Ok(ChunkType {
@@ -89,7 +104,7 @@ impl FromStr for ChunkType {
                 safe_to_copy: res[3],
             })
         } else {
-            Err("Length not correct".into())
+            Err(PngSecretError::InvalidChunkType(s.to_string()))
         }
     }
 }
@@ -118,6 +133,12 @@ mod tests {
         assert_eq!(expected, actual.bytes());
     }
 
+    #[test]
+    pub fn test_invalid_chunk_type_from_bytes() {
+        let actual = ChunkType::try_from([82, 117, 32, 116]);
+        assert!(matches!(actual, Err(PngSecretError::InvalidChunkType(_))));
+    }
+
     #[test]
     pub fn test_chunk_type_from_str() {
         let expected = ChunkType::try_from([82, 117, 83, 116]).unwrap();
@@ -189,6 +210,13 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    pub fn test_chunk_type_is_standard() {
+        assert!(ChunkType::from_str("IHDR").unwrap().is_standard());
+        assert!(ChunkType::from_str("sBIT").unwrap().is_standard());
+        assert!(!ChunkType::from_str("RuSt").unwrap().is_standard());
+    }
+
     #[test]
     pub fn test_chunk_type_string() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
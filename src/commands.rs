@@ -1,6 +1,9 @@
 use std::path::PathBuf;
 
-use clap::Subcommand;
+use clap::{Args, Subcommand, ValueEnum};
+use png_secret::compression::Compression;
+use png_secret::recipient::Recipient;
+use png_secret::Channels;
 
 
 #[derive(Subcommand)]
@@ -8,13 +11,83 @@ pub enum Commands {
     Encode {
         file_path: PathBuf,
         chunk_type: String,
-        message: String,
-        output_file: Option<PathBuf>
+        /// The message to hide
+        #[arg(required_unless_present = "file", conflicts_with = "file")]
+        message: Option<String>,
+        output_file: Option<PathBuf>,
+
+        /// Hide this file, with its name and MIME type, instead of a message
+        #[arg(long, value_name = "PATH")]
+        file: Option<PathBuf>,
+
+        /// Put the secret before the image data instead of right before IEND
+        #[arg(long)]
+        before_idat: bool,
+
+        /// Store the message in a standard text chunk (tEXt, zTXt or iTXt) under this keyword
+        #[arg(long, value_name = "KEYWORD")]
+        as_text: Option<String>,
+
+        /// Compress the secret before hiding it, with deflate or zstd
+        #[arg(long, value_name = "ALGORITHM", num_args = 0..=1, default_missing_value = "deflate")]
+        compress: Option<Compression>,
+
+        /// Spread the secret over several chunks holding at most this many bytes each
+        #[arg(long, value_name = "BYTES")]
+        split: Option<usize>,
+
+        /// Remove the secrets already stored under this chunk type
+        #[arg(long, group = "on_existing")]
+        replace: bool,
+
+        /// Keep the secrets already stored under this chunk type, the default with chunks
+        #[arg(long, group = "on_existing")]
+        append: bool,
+
+        /// Fail if a secret is already stored under this chunk type
+        #[arg(long, group = "on_existing")]
+        fail_if_exists: bool,
+
+        #[command(flatten)]
+        lsb: LsbArgs,
+
+        #[command(flatten)]
+        key: KeyArgs,
+
+        /// Encrypt the message for this public key, can be repeated
+        #[arg(long = "recipient", value_name = "PUBLIC_KEY", conflicts_with_all = ["passphrase", "key_file"])]
+        recipients: Vec<Recipient>,
     },
 
     Decode {
         file_path: PathBuf,
         chunk_type: String,
+
+        /// Read the message from the text chunk with this keyword
+        #[arg(long, value_name = "KEYWORD")]
+        as_text: Option<String>,
+
+        /// Read every secret stored under this chunk type
+        #[arg(long, conflicts_with = "index")]
+        all: bool,
+
+        /// Read the secret at this position among the ones stored under this chunk type, from 0
+        #[arg(long, value_name = "N")]
+        index: Option<usize>,
+
+        #[command(flatten)]
+        lsb: LsbArgs,
+
+        #[command(flatten)]
+        key: KeyArgs,
+
+        /// Identity file to decrypt a message encrypted for recipients
+        #[arg(long, value_name = "PATH")]
+        identity: Option<PathBuf>,
+
+        /// Restore a hidden file in this directory
+        #[arg(short, long, value_name = "DIR")]
+        output: Option<PathBuf>,
     },
 
     Remove {
@@ -24,5 +97,73 @@ pub enum Commands {
 
     Print {
         file_path: PathBuf,
-    }
+    },
+
+    /// List every chunk with its offset, length, flags, CRC and a summary of its data
+    Inspect {
+        file_path: PathBuf,
+    },
+
+    /// Create an identity to decrypt messages, and its public key
+    Keygen {
+        /// Write the identity to this file instead of printing it
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+
+    /// Public keys of encrypted messages
+    Recipients {
+        #[command(subcommand)]
+        command: RecipientsCommands,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RecipientsCommands {
+    /// Print the fingerprints of the public keys a message is encrypted for
+    List {
+        file_path: PathBuf,
+        chunk_type: String,
+
+        #[command(flatten)]
+        lsb: LsbArgs,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Method {
+    /// In its own chunk, the chunk type is the key
+    Chunk,
+    /// In the least significant bits of the pixels, the chunk type tags the message
+    Lsb,
+}
+
+#[derive(Args)]
+pub struct LsbArgs {
+    /// Where the message is hidden
+    #[arg(long, value_enum, default_value_t = Method::Chunk)]
+    pub method: Method,
+
+    /// Low bits of each sample used by the lsb method
+    #[arg(long, default_value_t = 1)]
+    pub bits_per_channel: u8,
+
+    /// Samples used by the lsb method, among r, g, b and a
+    #[arg(long, default_value = "rgb")]
+    pub channels: Channels,
+
+    /// Key choosing the pixel order of the lsb method, needed again to decode
+    #[arg(long, value_name = "KEY")]
+    pub lsb_key: Option<String>,
+}
+
+#[derive(Args)]
+pub struct KeyArgs {
+    /// Passphrase the message is encrypted with, asked when decoding if missing
+    #[arg(long, conflicts_with = "key_file")]
+    pub passphrase: Option<String>,
+
+    /// File whose content the message is encrypted with
+    #[arg(long, value_name = "PATH")]
+    pub key_file: Option<PathBuf>,
 }
@@ -0,0 +1,160 @@
+//! Compression of secret payloads, before they are encrypted and hidden.
+//!
+//! A compressed payload starts with one byte naming the algorithm, 0 for a
+//! zlib stream and 1 for a zstd frame, followed by the compressed data.
+//! zstd needs the `zstd` feature.
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::zlib;
+use crate::{PngSecretError, Result};
+
+const DEFLATE: u8 = 0;
+const ZSTD: u8 = 1;
+
+/// The compression algorithm of a payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    Deflate,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    fn value(&self) -> u8 {
+        match self {
+            Compression::Deflate => DEFLATE,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => ZSTD,
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = PngSecretError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "deflate" => Ok(Compression::Deflate),
+            #[cfg(feature = "zstd")]
+            "zstd" => Ok(Compression::Zstd),
+            #[cfg(not(feature = "zstd"))]
+            "zstd" => Err(unsupported()),
+            _ => Err(PngSecretError::InvalidEnvelope(format!("unknown compression '{}'", s))),
+        }
+    }
+}
+
+impl Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Compression::Deflate => write!(f, "deflate"),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+/// Compress `data` with `compression`, the algorithm is stored in front.
+pub fn compress(data: &[u8], compression: Compression) -> Vec<u8> {
+    let compressed = match compression {
+        Compression::Deflate => zlib::compress(data, 9),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => zstd::encode_all(data, 19).expect("compressing in memory can't fail"),
+    };
+
+    let mut output = Vec::with_capacity(1 + compressed.len());
+    output.push(compression.value());
+    output.extend(compressed);
+    output
+}
+
+/// Decompress data made by [`compress`], failing with
+/// [`PngSecretError::DecompressionLimit`] if it is bigger than `limit` bytes.
+pub fn decompress(data: &[u8], limit: usize) -> Result<Vec<u8>> {
+    match data.split_first() {
+        Some((&DEFLATE, compressed)) => zlib::decompress(compressed, limit),
+        Some((&ZSTD, compressed)) => decompress_zstd(compressed, limit),
+        Some((algorithm, _)) => Err(PngSecretError::InvalidEnvelope(format!("unknown compression {}", algorithm))),
+        None => Err(PngSecretError::InvalidEnvelope("empty compressed payload".to_string())),
+    }
+}
+
+#[cfg(feature = "zstd")]
+fn decompress_zstd(compressed: &[u8], limit: usize) -> Result<Vec<u8>> {
+    use std::io::Read;
+
+    // read one byte past the limit to know if it is reached
+    let mut output = Vec::new();
+    zstd::Decoder::new(compressed)?
+        .take(limit as u64 + 1)
+        .read_to_end(&mut output)
+        .map_err(|e| PngSecretError::InvalidEnvelope(format!("invalid zstd data : {}", e)))?;
+
+    if output.len() > limit {
+        return Err(PngSecretError::DecompressionLimit(limit));
+    }
+    Ok(output)
+}
+
+#[cfg(not(feature = "zstd"))]
+fn decompress_zstd(_compressed: &[u8], _limit: usize) -> Result<Vec<u8>> {
+    Err(unsupported())
+}
+
+#[cfg(not(feature = "zstd"))]
+fn unsupported() -> PngSecretError {
+    PngSecretError::InvalidEnvelope("zstd compression needs the zstd feature".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Vec<u8> {
+        br#"{"name": "server", "port": 8080, "tls": true}"#.repeat(20)
+    }
+
+    #[test]
+    fn test_deflate() {
+        let compressed = compress(&config(), Compression::Deflate);
+        assert_eq!(compressed[0], 0);
+        assert!(compressed.len() < config().len() / 4);
+        assert_eq!(decompress(&compressed, 1 << 20).unwrap(), config());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd() {
+        let compressed = compress(&config(), Compression::Zstd);
+        assert_eq!(compressed[0], 1);
+        assert_eq!(decompress(&compressed, 1 << 20).unwrap(), config());
+        assert!(matches!(decompress(&compressed, 100), Err(PngSecretError::DecompressionLimit(100))));
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    #[test]
+    fn test_zstd_disabled() {
+        assert!("zstd".parse::<Compression>().is_err());
+        assert!(decompress(&[1, 0x28, 0xb5, 0x2f, 0xfd], 1 << 20).is_err());
+    }
+
+    #[test]
+    fn test_limit() {
+        // a decompression bomb
+        let compressed = compress(&vec![0; 1 << 20], Compression::Deflate);
+        assert!(compressed.len() < 2000);
+        assert!(matches!(decompress(&compressed, 1 << 16), Err(PngSecretError::DecompressionLimit(_))));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(decompress(&[], 100).is_err());
+        assert!(decompress(&[7, 1, 2], 100).is_err());
+        assert!(decompress(&[0, 1, 2], 100).is_err());
+        assert_eq!("deflate".parse::<Compression>().unwrap(), Compression::Deflate);
+        assert!("lzma".parse::<Compression>().is_err());
+    }
+}
@@ -0,0 +1,460 @@
+//! Authenticated encryption of secret payloads.
+//!
+//! The payload is sealed with ChaCha20-Poly1305. Its key is derived from a
+//! passphrase or the content of a key file with Argon2id, or it is a random
+//! file key wrapped for X25519 recipients (see [`crate::recipient`]). An
+//! encrypted payload starts with :
+//!
+//! | bytes | content                                                            |
+//! |-------|--------------------------------------------------------------------|
+//! | 5     | magic `PSENC`                                                      |
+//! | 1     | version, 2                                                         |
+//! | 1     | key source, 0 for a passphrase, 1 for a key file, 2 for recipients |
+//!
+//! With a passphrase or a key file, it goes on with :
+//!
+//! | bytes | content                                       |
+//! |-------|-----------------------------------------------|
+//! | 12    | Argon2 memory (KiB), passes and lanes, u32 BE |
+//! | 16    | salt                                          |
+//!
+//! With recipients, it goes on with the number of recipients on 1 byte and
+//! one 88 bytes wrapped file key for each of them.
+//!
+//! Then come the 12 bytes nonce, and the ciphertext followed by its 16 bytes
+//! tag. Everything before the ciphertext is authenticated with it, and so is
+//! the associated data given by the caller, the envelope header for secrets.
+//! Version 1 payloads, written before that, are still decrypted without it.
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, KeyInit, Nonce};
+
+use crate::recipient::{self, Identity, Recipient, FILE_KEY_LENGTH, STANZA_LENGTH};
+use crate::{PngSecretError, Result};
+
+const MAGIC: &[u8; 5] = b"PSENC";
+const VERSION: u8 = 2;
+// without associated data
+const VERSION_1: u8 = 1;
+const START_LENGTH: usize = MAGIC.len() + 2;
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+// with a passphrase or a key file
+const HEADER_LENGTH: usize = START_LENGTH + 12 + SALT_LENGTH + NONCE_LENGTH;
+const TAG_LENGTH: usize = 16;
+
+// `encrypt` derives keys with 19 MiB, 2 passes and 1 lane, refuse more than
+// this so a crafted file can't make the decoder spend more than 256 MiB and
+// 10 passes over it before the tag is checked
+const MAX_MEMORY: u32 = 256 * 1024;
+const MAX_PASSES: u32 = 10;
+const MAX_LANES: u32 = 4;
+
+/// What the encryption key is derived from.
+#[derive(Clone, PartialEq, Eq)]
+pub enum Key {
+    Passphrase(String),
+    /// The content of a key file.
+    KeyFile(Vec<u8>),
+}
+
+impl Key {
+    fn source(&self) -> KeySource {
+        match self {
+            Key::Passphrase(_) => KeySource::Passphrase,
+            Key::KeyFile(_) => KeySource::KeyFile,
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Key::Passphrase(passphrase) => passphrase.as_bytes(),
+            Key::KeyFile(content) => content,
+        }
+    }
+}
+
+// don't print the key in logs
+impl std::fmt::Debug for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Key::Passphrase(_) => write!(f, "Passphrase(..)"),
+            Key::KeyFile(_) => write!(f, "KeyFile(..)"),
+        }
+    }
+}
+
+/// Which kind of [`Key`] an encrypted payload was sealed with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeySource {
+    Passphrase,
+    KeyFile,
+    /// Public keys, decrypted with an [`Identity`].
+    Recipients,
+}
+
+impl KeySource {
+    fn value(&self) -> u8 {
+        match self {
+            KeySource::Passphrase => 0,
+            KeySource::KeyFile => 1,
+            KeySource::Recipients => 2,
+        }
+    }
+}
+
+/// Whether `data` starts like an encrypted payload.
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// The kind of key needed to decrypt `data`, `None` if it isn't encrypted.
+pub fn key_source(data: &[u8]) -> Option<KeySource> {
+    if !is_encrypted(data) {
+        return None;
+    }
+    match data.get(MAGIC.len() + 1) {
+        Some(0) => Some(KeySource::Passphrase),
+        Some(1) => Some(KeySource::KeyFile),
+        Some(2) => Some(KeySource::Recipients),
+        _ => None,
+    }
+}
+
+/// Encrypt `plaintext` with a key derived from `key` and a random salt,
+/// authenticating `associated` with it.
+pub fn encrypt(plaintext: &[u8], key: &Key, associated: &[u8]) -> Result<Vec<u8>> {
+    encrypt_with(plaintext, key, associated, Params::default())
+}
+
+fn encrypt_with(plaintext: &[u8], key: &Key, associated: &[u8], params: Params) -> Result<Vec<u8>> {
+    let mut salt = [0; SALT_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+
+    let mut header = start(key.source());
+    header.extend_from_slice(&params.m_cost().to_be_bytes());
+    header.extend_from_slice(&params.t_cost().to_be_bytes());
+    header.extend_from_slice(&params.p_cost().to_be_bytes());
+    header.extend_from_slice(&salt);
+
+    seal(derive_key(key, &salt, params)?, header, plaintext, associated)
+}
+
+/// Encrypt `plaintext` for `recipients`, any of their identities can decrypt
+/// it, authenticating `associated` with it.
+pub fn encrypt_to(plaintext: &[u8], recipients: &[Recipient], associated: &[u8]) -> Result<Vec<u8>> {
+    if recipients.is_empty() || recipients.len() > u8::MAX as usize {
+        return Err(PngSecretError::InvalidKey(format!("between 1 and {} recipients are needed", u8::MAX)));
+    }
+
+    let mut file_key = [0; FILE_KEY_LENGTH];
+    OsRng.fill_bytes(&mut file_key);
+
+    let mut header = start(KeySource::Recipients);
+    header.push(recipients.len() as u8);
+    for recipient in recipients {
+        header.extend(recipient.wrap_file_key(&file_key));
+    }
+
+    seal(file_key, header, plaintext, associated)
+}
+
+// magic, version and key source
+fn start(source: KeySource) -> Vec<u8> {
+    let mut header = Vec::with_capacity(HEADER_LENGTH);
+    header.extend_from_slice(MAGIC);
+    header.push(VERSION);
+    header.push(source.value());
+    header
+}
+
+// add a random nonce to `header`, then `plaintext` encrypted with `key`
+fn seal(key: [u8; 32], mut header: Vec<u8>, plaintext: &[u8], associated: &[u8]) -> Result<Vec<u8>> {
+    let mut nonce = [0; NONCE_LENGTH];
+    OsRng.fill_bytes(&mut nonce);
+    header.extend_from_slice(&nonce);
+
+    let aad = [header.as_slice(), associated].concat();
+    let ciphertext = ChaCha20Poly1305::new(&key.into())
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad: &aad })
+        .map_err(|_| PngSecretError::InvalidEncryptedData("encryption failed".to_string()))?;
+
+    header.extend(ciphertext);
+    Ok(header)
+}
+
+// decrypt the ciphertext after the `header_length` first bytes of `data`,
+// the nonce ends the header
+fn open(key: [u8; 32], data: &[u8], header_length: usize, associated: &[u8]) -> Result<Vec<u8>> {
+    let (header, ciphertext) = data.split_at(header_length);
+    let nonce = &header[header_length - NONCE_LENGTH..];
+    let aad = match header[MAGIC.len()] {
+        VERSION_1 => header.to_vec(),
+        _ => [header, associated].concat(),
+    };
+
+    ChaCha20Poly1305::new(&key.into())
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: &aad })
+        .map_err(|_| PngSecretError::DecryptionFailed)
+}
+
+// check the magic and version, and that `data` is long enough to hold a
+// header of `header_length` bytes
+fn check(data: &[u8], header_length: usize) -> Result<()> {
+    let invalid = |reason: &str| PngSecretError::InvalidEncryptedData(reason.to_string());
+
+    if !is_encrypted(data) {
+        return Err(invalid("missing magic"));
+    }
+    if data.len() < START_LENGTH {
+        return Err(invalid("too short"));
+    }
+    if !(VERSION_1..=VERSION).contains(&data[MAGIC.len()]) {
+        return Err(invalid(&format!("unknown version {}", data[MAGIC.len()])));
+    }
+    if data.len() < header_length + TAG_LENGTH {
+        return Err(invalid("too short"));
+    }
+    Ok(())
+}
+
+/// Decrypt a payload made by [`encrypt`] with the same `associated` data.
+///
+/// Fails with [`PngSecretError::DecryptionFailed`] if the key is wrong or the
+/// payload or the associated data were modified.
+pub fn decrypt(data: &[u8], key: &Key, associated: &[u8]) -> Result<Vec<u8>> {
+    let invalid = |reason: &str| PngSecretError::InvalidEncryptedData(reason.to_string());
+
+    check(data, HEADER_LENGTH)?;
+    if key_source(data) != Some(key.source()) {
+        return Err(PngSecretError::DecryptionFailed);
+    }
+
+    let u32_at = |i: usize| u32::from_be_bytes(data[i..i + 4].try_into().unwrap());
+    let (m_cost, t_cost, p_cost) = (u32_at(7), u32_at(11), u32_at(15));
+    if m_cost > MAX_MEMORY {
+        return Err(invalid(&format!("key derivation needs {} KiB of memory", m_cost)));
+    }
+    if t_cost > MAX_PASSES {
+        return Err(invalid(&format!("key derivation needs {} passes", t_cost)));
+    }
+    if p_cost > MAX_LANES {
+        return Err(invalid(&format!("key derivation needs {} lanes", p_cost)));
+    }
+    let params = Params::new(m_cost, t_cost, p_cost, None).map_err(|e| invalid(&e.to_string()))?;
+
+    let salt = &data[19..19 + SALT_LENGTH];
+    open(derive_key(key, salt, params)?, data, HEADER_LENGTH, associated)
+}
+
+/// Decrypt a payload made by [`encrypt_to`] with the identity of one of its
+/// recipients and the same `associated` data.
+pub fn decrypt_with(data: &[u8], identity: &Identity, associated: &[u8]) -> Result<Vec<u8>> {
+    let stanzas = stanzas(data)?;
+    let header_length = START_LENGTH + 1 + stanzas.len() + NONCE_LENGTH;
+
+    let file_key = stanzas
+        .chunks(STANZA_LENGTH)
+        .find_map(|stanza| identity.unwrap_file_key(stanza))
+        .ok_or(PngSecretError::DecryptionFailed)?;
+    open(file_key, data, header_length, associated)
+}
+
+/// Fingerprints of the recipients a payload made by [`encrypt_to`] can be
+/// decrypted by, see [`Recipient::fingerprint`].
+pub fn recipients(data: &[u8]) -> Result<Vec<String>> {
+    Ok(stanzas(data)?.chunks(STANZA_LENGTH).map(recipient::stanza_fingerprint).collect())
+}
+
+// the wrapped file keys of a payload encrypted for recipients
+fn stanzas(data: &[u8]) -> Result<&[u8]> {
+    check(data, START_LENGTH + 1)?;
+    if key_source(data) != Some(KeySource::Recipients) {
+        return Err(PngSecretError::InvalidEncryptedData("not encrypted for recipients".to_string()));
+    }
+
+    let count = data[START_LENGTH] as usize;
+    let stanzas_end = START_LENGTH + 1 + count * STANZA_LENGTH;
+    check(data, stanzas_end + NONCE_LENGTH)?;
+    Ok(&data[START_LENGTH + 1..stanzas_end])
+}
+
+fn derive_key(key: &Key, salt: &[u8], params: Params) -> Result<[u8; 32]> {
+    let mut derived = [0; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(key.bytes(), salt, &mut derived)
+        .map_err(|e| PngSecretError::InvalidEncryptedData(e.to_string()))?;
+    Ok(derived)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the default parameters are slow in debug builds
+    fn fast_params() -> Params {
+        Params::new(64, 1, 1, None).unwrap()
+    }
+
+    fn passphrase(passphrase: &str) -> Key {
+        Key::Passphrase(passphrase.to_string())
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for key in [passphrase("hunter2"), Key::KeyFile(vec![0, 1, 2, 255])] {
+            let data = encrypt_with(b"My secret", &key, b"", fast_params()).unwrap();
+            assert!(is_encrypted(&data));
+            assert_eq!(key_source(&data), Some(key.source()));
+            assert_eq!(data.len(), HEADER_LENGTH + 9 + TAG_LENGTH);
+            assert_eq!(decrypt(&data, &key, b"").unwrap(), b"My secret");
+        }
+    }
+
+    #[test]
+    fn test_hides_plaintext() {
+        let data = encrypt_with(b"My secret", &passphrase("hunter2"), b"", fast_params()).unwrap();
+        assert!(!data.windows(9).any(|window| window == b"My secret"));
+
+        // fresh salt and nonce every time
+        let other = encrypt_with(b"My secret", &passphrase("hunter2"), b"", fast_params()).unwrap();
+        assert_ne!(data, other);
+    }
+
+    #[test]
+    fn test_wrong_key() {
+        let data = encrypt_with(b"My secret", &passphrase("hunter2"), b"", fast_params()).unwrap();
+        assert!(matches!(decrypt(&data, &passphrase("hunter3"), b""), Err(PngSecretError::DecryptionFailed)));
+        assert!(matches!(
+            decrypt(&data, &Key::KeyFile(b"hunter2".to_vec()), b""),
+            Err(PngSecretError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn test_tampered() {
+        let key = passphrase("hunter2");
+        let data = encrypt_with(b"My secret", &key, b"", fast_params()).unwrap();
+
+        let mut tampered = data.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(matches!(decrypt(&tampered, &key, b""), Err(PngSecretError::DecryptionFailed)));
+
+        // the header is authenticated too
+        let mut tampered = data.clone();
+        tampered[20] ^= 1;
+        assert!(matches!(decrypt(&tampered, &key, b""), Err(PngSecretError::DecryptionFailed)));
+
+        // and so is the associated data
+        let data = encrypt_with(b"My secret", &key, b"header", fast_params()).unwrap();
+        assert_eq!(decrypt(&data, &key, b"header").unwrap(), b"My secret");
+        assert!(matches!(decrypt(&data, &key, b"Header"), Err(PngSecretError::DecryptionFailed)));
+        let data = encrypt_to(b"My secret", &[Identity::generate().recipient()], b"header").unwrap();
+        assert!(matches!(decrypt_with(&data, &Identity::generate(), b"Header"), Err(PngSecretError::DecryptionFailed)));
+    }
+
+    #[test]
+    fn test_version_1() {
+        // written before the associated data, which is then ignored
+        let key = passphrase("hunter2");
+        let params = fast_params();
+        let salt = [7; SALT_LENGTH];
+        let mut header = start(key.source());
+        header[MAGIC.len()] = VERSION_1;
+        header.extend_from_slice(&params.m_cost().to_be_bytes());
+        header.extend_from_slice(&params.t_cost().to_be_bytes());
+        header.extend_from_slice(&params.p_cost().to_be_bytes());
+        header.extend_from_slice(&salt);
+        let data = seal(derive_key(&key, &salt, params).unwrap(), header, b"My secret", b"").unwrap();
+
+        assert_eq!(decrypt(&data, &key, b"header").unwrap(), b"My secret");
+    }
+
+    #[test]
+    fn test_invalid() {
+        let key = passphrase("hunter2");
+        assert!(!is_encrypted(b"My secret"));
+        assert_eq!(key_source(b"My secret"), None);
+        assert!(matches!(decrypt(b"My secret", &key, b""), Err(PngSecretError::InvalidEncryptedData(_))));
+        assert!(matches!(decrypt(b"PSENC\x01\x00", &key, b""), Err(PngSecretError::InvalidEncryptedData(_))));
+
+        let mut data = encrypt_with(b"My secret", &key, b"", fast_params()).unwrap();
+        data[5] = 3;
+        assert!(matches!(decrypt(&data, &key, b""), Err(PngSecretError::InvalidEncryptedData(_))));
+
+        let mut data = encrypt_with(b"My secret", &key, b"", fast_params()).unwrap();
+        data[7..11].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(decrypt(&data, &key, b""), Err(PngSecretError::InvalidEncryptedData(_))));
+    }
+
+    #[test]
+    fn test_too_much_memory() {
+        let key = passphrase("hunter2");
+        let mut data = encrypt_with(b"My secret", &key, b"", fast_params()).unwrap();
+        data[7..11].copy_from_slice(&(MAX_MEMORY + 1).to_be_bytes());
+        assert!(matches!(decrypt(&data, &key, b""), Err(PngSecretError::InvalidEncryptedData(reason)) if reason.contains("memory")));
+    }
+
+    #[test]
+    fn test_default_params_allowed() {
+        let params = Params::default();
+        assert!(params.m_cost() <= MAX_MEMORY && params.t_cost() <= MAX_PASSES && params.p_cost() <= MAX_LANES);
+    }
+
+    #[test]
+    fn test_too_many_passes() {
+        let key = passphrase("hunter2");
+        let mut data = encrypt_with(b"My secret", &key, b"", fast_params()).unwrap();
+        data[11..15].copy_from_slice(&(MAX_PASSES + 1).to_be_bytes());
+        assert!(matches!(decrypt(&data, &key, b""), Err(PngSecretError::InvalidEncryptedData(reason)) if reason.contains("passes")));
+    }
+
+    #[test]
+    fn test_too_many_lanes() {
+        let key = passphrase("hunter2");
+        let mut data = encrypt_with(b"My secret", &key, b"", fast_params()).unwrap();
+        data[7..11].copy_from_slice(&MAX_MEMORY.to_be_bytes());
+        data[15..19].copy_from_slice(&(MAX_LANES + 1).to_be_bytes());
+        assert!(matches!(decrypt(&data, &key, b""), Err(PngSecretError::InvalidEncryptedData(reason)) if reason.contains("lanes")));
+    }
+
+    #[test]
+    fn test_recipients() {
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+        let eve = Identity::generate();
+
+        let data = encrypt_to(b"My secret", &[alice.recipient(), bob.recipient()], b"").unwrap();
+        assert_eq!(key_source(&data), Some(KeySource::Recipients));
+        assert_eq!(decrypt_with(&data, &alice, b"").unwrap(), b"My secret");
+        assert_eq!(decrypt_with(&data, &bob, b"").unwrap(), b"My secret");
+        assert!(matches!(decrypt_with(&data, &eve, b""), Err(PngSecretError::DecryptionFailed)));
+        assert!(matches!(decrypt(&data, &passphrase("hunter2"), b""), Err(PngSecretError::DecryptionFailed)));
+
+        assert_eq!(
+            recipients(&data).unwrap(),
+            vec![alice.recipient().fingerprint(), bob.recipient().fingerprint()]
+        );
+    }
+
+    #[test]
+    fn test_recipients_invalid() {
+        let alice = Identity::generate();
+        assert!(matches!(encrypt_to(b"My secret", &[], b""), Err(PngSecretError::InvalidKey(_))));
+
+        let data = encrypt_with(b"My secret", &passphrase("hunter2"), b"", fast_params()).unwrap();
+        assert!(matches!(recipients(&data), Err(PngSecretError::InvalidEncryptedData(_))));
+
+        // more recipients announced than stored
+        let mut data = encrypt_to(b"My secret", &[alice.recipient()], b"").unwrap();
+        data[START_LENGTH] = 9;
+        assert!(matches!(decrypt_with(&data, &alice, b""), Err(PngSecretError::InvalidEncryptedData(_))));
+
+        // the recipient list is authenticated
+        let mut data = encrypt_to(b"My secret", &[alice.recipient(), Identity::generate().recipient()], b"").unwrap();
+        data[START_LENGTH + 1 + STANZA_LENGTH] ^= 1;
+        assert!(matches!(decrypt_with(&data, &alice, b""), Err(PngSecretError::DecryptionFailed)));
+    }
+}
@@ -0,0 +1,497 @@
+//! The header in front of every secret, so it can be told apart from the
+//! data of an ordinary chunk.
+//!
+//! | bytes | content                                              |
+//! |-------|------------------------------------------------------|
+//! | 4     | magic `PSEC`                                         |
+//! | 1     | version, 1                                           |
+//! | 1     | flags, 1 compressed, 2 encrypted, 4 split            |
+//! | 1     | content type, 0 text, 1 binary data, 2 file          |
+//! | 4     | length of the payload, u32 BE                        |
+//! | 4     | CRC-32 of the payload, u32 BE                        |
+//! | ..    | payload                                              |
+//!
+//! The version, flags and content type of an encrypted secret are
+//! authenticated with its payload, see [`crate::crypto`].
+//!
+//! Secrets written before the envelope are only their payload, they are
+//! still read as text, or as encrypted data if they start like it.
+
+use crate::attachment::Attachment;
+use crate::chunk;
+use crate::compression::{self, Compression};
+use crate::crypto::{self, Key, KeySource};
+use crate::recipient::{Identity, Recipient};
+use crate::{PngSecretError, Result};
+
+const MAGIC: &[u8; 4] = b"PSEC";
+const VERSION: u8 = 1;
+const HEADER_LENGTH: usize = 15;
+
+const COMPRESSED: u8 = 1;
+const ENCRYPTED: u8 = 2;
+const SPLIT: u8 = 4;
+
+/// How the payload of an [`Envelope`] was transformed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Flags {
+    pub compressed: bool,
+    pub encrypted: bool,
+    /// The payload is one part of a secret spread over several chunks.
+    pub split: bool,
+}
+
+impl Flags {
+    fn value(&self) -> u8 {
+        let flag = |set: bool, value: u8| if set { value } else { 0 };
+        flag(self.compressed, COMPRESSED) | flag(self.encrypted, ENCRYPTED) | flag(self.split, SPLIT)
+    }
+}
+
+impl TryFrom<u8> for Flags {
+    type Error = PngSecretError;
+
+    fn try_from(value: u8) -> Result<Self> {
+        if value & !(COMPRESSED | ENCRYPTED | SPLIT) != 0 {
+            return Err(PngSecretError::InvalidEnvelope(format!("unknown flags {:#04x}", value)));
+        }
+        Ok(Flags {
+            compressed: value & COMPRESSED != 0,
+            encrypted: value & ENCRYPTED != 0,
+            split: value & SPLIT != 0,
+        })
+    }
+}
+
+/// What the payload holds once decrypted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ContentType {
+    /// UTF-8 text.
+    #[default]
+    Text,
+    Binary,
+    /// A file with its name and MIME type, see [`Attachment`].
+    File,
+}
+
+impl ContentType {
+    fn value(&self) -> u8 {
+        match self {
+            ContentType::Text => 0,
+            ContentType::Binary => 1,
+            ContentType::File => 2,
+        }
+    }
+}
+
+impl TryFrom<u8> for ContentType {
+    type Error = PngSecretError;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(ContentType::Text),
+            1 => Ok(ContentType::Binary),
+            2 => Ok(ContentType::File),
+            _ => Err(PngSecretError::InvalidEnvelope(format!("unknown content type {}", value))),
+        }
+    }
+}
+
+/// A secret payload with its header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    pub flags: Flags,
+    pub content_type: ContentType,
+    pub payload: Vec<u8>,
+}
+
+impl Envelope {
+    pub fn new(content_type: ContentType, payload: Vec<u8>) -> Envelope {
+        Envelope {
+            flags: Flags::default(),
+            content_type,
+            payload,
+        }
+    }
+
+    /// Whether `data` starts with an envelope header.
+    pub fn is_envelope(data: &[u8]) -> bool {
+        data.starts_with(MAGIC)
+    }
+
+    /// Read an envelope, or a secret written before the envelope existed.
+    pub fn read(data: &[u8]) -> Result<Envelope> {
+        if Envelope::is_envelope(data) {
+            return Envelope::try_from(data);
+        }
+
+        Ok(Envelope {
+            flags: Flags {
+                encrypted: crypto::is_encrypted(data),
+                ..Flags::default()
+            },
+            content_type: ContentType::Text,
+            payload: data.to_vec(),
+        })
+    }
+
+    /// The envelope header followed by the payload.
+    ///
+    /// Fails with [`PngSecretError::PayloadTooLarge`] if the payload length
+    /// doesn't fit in the header.
+    pub fn as_bytes(&self) -> Result<Vec<u8>> {
+        let length = payload_length(self.payload.len())?;
+        let mut bytes = Vec::with_capacity(HEADER_LENGTH + self.payload.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.push(self.flags.value());
+        bytes.push(self.content_type.value());
+        bytes.extend_from_slice(&length.to_be_bytes());
+        bytes.extend_from_slice(&checksum(&self.payload).to_be_bytes());
+        bytes.extend_from_slice(&self.payload);
+        Ok(bytes)
+    }
+
+    /// The payload as text.
+    ///
+    /// Fails with [`PngSecretError::KeyNeeded`] if it is still encrypted.
+    pub fn text(&self) -> Result<String> {
+        self.check_readable()?;
+        if self.content_type == ContentType::File {
+            return Err(PngSecretError::InvalidEnvelope("the secret is a file, not text".to_string()));
+        }
+        Ok(String::from_utf8(self.payload.clone())?)
+    }
+
+    /// The file of a payload with the [`ContentType::File`] content type.
+    pub fn attachment(&self) -> Result<Attachment> {
+        self.check_readable()?;
+        if self.content_type != ContentType::File {
+            return Err(PngSecretError::InvalidEnvelope("the secret isn't a file".to_string()));
+        }
+        Attachment::try_from(self.payload.as_slice())
+    }
+
+    fn check_readable(&self) -> Result<()> {
+        if self.flags.encrypted {
+            return Err(PngSecretError::KeyNeeded);
+        }
+        if self.flags.split {
+            return Err(PngSecretError::InvalidEnvelope("only a part of a split secret".to_string()));
+        }
+        if self.flags.compressed {
+            return Err(PngSecretError::InvalidEnvelope("the payload must be decompressed first".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Compress the payload, before it is encrypted.
+    pub fn compress(self, compression: Compression) -> Result<Envelope> {
+        if self.flags.encrypted {
+            return Err(PngSecretError::InvalidEnvelope("an encrypted payload can't be compressed".to_string()));
+        }
+        Ok(Envelope {
+            flags: Flags { compressed: true, ..self.flags },
+            payload: compression::compress(&self.payload, compression),
+            ..self
+        })
+    }
+
+    /// Decompress the payload if it is compressed, failing with
+    /// [`PngSecretError::DecompressionLimit`] if it is bigger than `limit`
+    /// bytes.
+    pub fn decompress(self, limit: usize) -> Result<Envelope> {
+        if !self.flags.compressed {
+            return Ok(self);
+        }
+        if self.flags.encrypted {
+            return Err(PngSecretError::KeyNeeded);
+        }
+        Ok(Envelope {
+            flags: Flags { compressed: false, ..self.flags },
+            payload: compression::decompress(&self.payload, limit)?,
+            ..self
+        })
+    }
+
+    /// Encrypt the payload with a key derived from `key`.
+    pub fn encrypt(self, key: &Key) -> Result<Envelope> {
+        let payload = crypto::encrypt(&self.payload, key, &self.associated_data())?;
+        Ok(self.encrypted(payload))
+    }
+
+    /// Encrypt the payload for `recipients`.
+    pub fn encrypt_to(self, recipients: &[Recipient]) -> Result<Envelope> {
+        let payload = crypto::encrypt_to(&self.payload, recipients, &self.associated_data())?;
+        Ok(self.encrypted(payload))
+    }
+
+    /// Decrypt a payload encrypted with [`Envelope::encrypt`].
+    pub fn decrypt(self, key: &Key) -> Result<Envelope> {
+        let payload = crypto::decrypt(&self.payload, key, &self.associated_data())?;
+        Ok(self.decrypted(payload))
+    }
+
+    /// Decrypt a payload encrypted with [`Envelope::encrypt_to`].
+    pub fn decrypt_with(self, identity: &Identity) -> Result<Envelope> {
+        let payload = crypto::decrypt_with(&self.payload, identity, &self.associated_data())?;
+        Ok(self.decrypted(payload))
+    }
+
+    // the header bytes authenticated with an encrypted payload, as they are
+    // once it is encrypted and before it is split
+    fn associated_data(&self) -> Vec<u8> {
+        let flags = Flags { encrypted: true, split: false, ..self.flags };
+        [MAGIC.as_slice(), &[VERSION, flags.value(), self.content_type.value()]].concat()
+    }
+
+    /// What the payload is encrypted with, `None` if it isn't encrypted.
+    pub fn key_source(&self) -> Option<KeySource> {
+        if !self.flags.encrypted {
+            return None;
+        }
+        crypto::key_source(&self.payload)
+    }
+
+    /// Decrypt the payload with `key` or `identity`, whichever it was
+    /// encrypted for, then decompress it up to `limit` bytes.
+    ///
+    /// Fails with [`PngSecretError::KeyNeeded`] if the one needed is missing.
+    pub fn reveal(self, key: Option<&Key>, identity: Option<&Identity>, limit: usize) -> Result<Envelope> {
+        let envelope = match (self.key_source(), key, identity) {
+            _ if !self.flags.encrypted => self,
+            (Some(KeySource::Recipients), _, Some(identity)) => self.decrypt_with(identity)?,
+            (Some(KeySource::Recipients), _, None) | (_, None, _) => return Err(PngSecretError::KeyNeeded),
+            (_, Some(key), _) => self.decrypt(key)?,
+        };
+        envelope.decompress(limit)
+    }
+
+    fn encrypted(self, payload: Vec<u8>) -> Envelope {
+        Envelope {
+            flags: Flags { encrypted: true, ..self.flags },
+            payload,
+            ..self
+        }
+    }
+
+    fn decrypted(self, payload: Vec<u8>) -> Envelope {
+        Envelope {
+            flags: Flags { encrypted: false, ..self.flags },
+            payload,
+            ..self
+        }
+    }
+}
+
+// the length of a payload as written in the header
+fn payload_length(length: usize) -> Result<u32> {
+    u32::try_from(length).map_err(|_| PngSecretError::PayloadTooLarge(length))
+}
+
+impl TryFrom<&[u8]> for Envelope {
+    type Error = PngSecretError;
+
+    fn try_from(data: &[u8]) -> Result<Self> {
+        let invalid = |reason: String| PngSecretError::InvalidEnvelope(reason);
+
+        if !Envelope::is_envelope(data) {
+            return Err(invalid("missing magic".to_string()));
+        }
+        if data.len() < HEADER_LENGTH {
+            return Err(invalid("truncated header".to_string()));
+        }
+        if data[4] != VERSION {
+            return Err(invalid(format!("unknown version {}", data[4])));
+        }
+
+        let flags = Flags::try_from(data[5])?;
+        let content_type = ContentType::try_from(data[6])?;
+        let length = u32::from_be_bytes(data[7..11].try_into().unwrap()) as usize;
+        let crc = u32::from_be_bytes(data[11..15].try_into().unwrap());
+
+        let payload = &data[HEADER_LENGTH..];
+        if payload.len() != length {
+            return Err(invalid(format!("the payload is {} bytes long, not {}", payload.len(), length)));
+        }
+        if checksum(payload) != crc {
+            return Err(invalid("the payload checksum doesn't match".to_string()));
+        }
+
+        Ok(Envelope {
+            flags,
+            content_type,
+            payload: payload.to_vec(),
+        })
+    }
+}
+
+impl From<&str> for Envelope {
+    fn from(text: &str) -> Self {
+        Envelope::new(ContentType::Text, text.as_bytes().to_vec())
+    }
+}
+
+impl From<String> for Envelope {
+    fn from(text: String) -> Self {
+        Envelope::new(ContentType::Text, text.into_bytes())
+    }
+}
+
+impl From<&Attachment> for Envelope {
+    fn from(attachment: &Attachment) -> Self {
+        Envelope::new(ContentType::File, attachment.as_bytes())
+    }
+}
+
+fn checksum(payload: &[u8]) -> u32 {
+    chunk::CRC_32.checksum(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut envelope = Envelope::new(ContentType::Binary, vec![0, 159, 146, 150]);
+        envelope.flags.split = true;
+
+        let bytes = envelope.as_bytes().unwrap();
+        assert_eq!(&bytes[..7], b"PSEC\x01\x04\x01");
+        assert_eq!(&bytes[7..11], &4u32.to_be_bytes());
+        assert_eq!(Envelope::try_from(bytes.as_slice()).unwrap(), envelope);
+        assert_eq!(Envelope::read(&bytes).unwrap(), envelope);
+    }
+
+    #[test]
+    fn test_text() {
+        let envelope = Envelope::from("My secret");
+        assert_eq!(envelope.content_type, ContentType::Text);
+        assert_eq!(Envelope::read(&envelope.as_bytes().unwrap()).unwrap().text().unwrap(), "My secret");
+
+        let binary = Envelope::new(ContentType::Binary, vec![0xff]);
+        assert!(binary.text().is_err());
+        assert!(envelope.attachment().is_err());
+    }
+
+    #[test]
+    fn test_attachment() {
+        let attachment = Attachment::new("config.json", b"{}".to_vec()).unwrap();
+        let envelope = Envelope::read(&Envelope::from(&attachment).as_bytes().unwrap()).unwrap();
+        assert_eq!(envelope.content_type, ContentType::File);
+        assert_eq!(envelope.attachment().unwrap(), attachment);
+        assert!(envelope.text().is_err());
+    }
+
+    #[test]
+    fn test_legacy() {
+        let envelope = Envelope::read(b"Old secret").unwrap();
+        assert_eq!(envelope, Envelope::from("Old secret"));
+        assert!(!Envelope::is_envelope(b"Old secret"));
+
+        let envelope = Envelope::read(b"PSENC\x01\x00").unwrap();
+        assert!(envelope.flags.encrypted);
+        assert!(matches!(envelope.text(), Err(PngSecretError::KeyNeeded)));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let bytes = Envelope::from("My secret").as_bytes().unwrap();
+
+        assert!(Envelope::try_from(b"My secret".as_slice()).is_err());
+        assert!(Envelope::read(&bytes[..10]).is_err());
+        assert!(Envelope::read(&bytes[..bytes.len() - 1]).is_err());
+
+        let mut corrupted = bytes.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        assert!(matches!(Envelope::read(&corrupted), Err(PngSecretError::InvalidEnvelope(_))));
+
+        for (index, value) in [(4, 2), (5, 8), (6, 9)] {
+            let mut corrupted = bytes.clone();
+            corrupted[index] = value;
+            assert!(matches!(Envelope::read(&corrupted), Err(PngSecretError::InvalidEnvelope(_))));
+        }
+    }
+
+    #[test]
+    fn test_payload_length() {
+        assert_eq!(payload_length(9).unwrap(), 9);
+        assert_eq!(payload_length(u32::MAX as usize).unwrap(), u32::MAX);
+        let length = u32::MAX as usize + 1;
+        assert!(matches!(payload_length(length), Err(PngSecretError::PayloadTooLarge(l)) if l == length));
+    }
+
+    #[test]
+    fn test_compress() {
+        let text = "A secret that repeats itself. ".repeat(10);
+        let envelope = Envelope::from(text.as_str()).compress(Compression::Deflate).unwrap();
+        assert!(envelope.flags.compressed);
+        assert!(envelope.payload.len() < text.len() / 2);
+        assert!(envelope.text().is_err());
+
+        let envelope = Envelope::read(&envelope.as_bytes().unwrap()).unwrap();
+        assert!(matches!(envelope.clone().decompress(100), Err(PngSecretError::DecompressionLimit(100))));
+        assert_eq!(envelope.decompress(1000).unwrap().text().unwrap(), text);
+
+        // compressed, then encrypted
+        let alice = Identity::generate();
+        let envelope = Envelope::from(text.as_str())
+            .compress(Compression::Deflate)
+            .unwrap()
+            .encrypt_to(&[alice.recipient()])
+            .unwrap();
+        assert!(envelope.flags.compressed && envelope.flags.encrypted);
+        assert!(envelope.clone().compress(Compression::Deflate).is_err());
+        assert!(matches!(envelope.clone().decompress(1000), Err(PngSecretError::KeyNeeded)));
+
+        let envelope = envelope.decrypt_with(&alice).unwrap().decompress(1000).unwrap();
+        assert_eq!(envelope.text().unwrap(), text);
+    }
+
+    #[test]
+    fn test_encrypt() {
+        let alice = Identity::generate();
+        let envelope = Envelope::from("My secret").encrypt_to(&[alice.recipient()]).unwrap();
+        assert!(envelope.flags.encrypted);
+        assert!(matches!(envelope.text(), Err(PngSecretError::KeyNeeded)));
+
+        let envelope = Envelope::read(&envelope.as_bytes().unwrap()).unwrap().decrypt_with(&alice).unwrap();
+        assert!(!envelope.flags.encrypted);
+        assert_eq!(envelope.text().unwrap(), "My secret");
+    }
+
+    #[test]
+    fn test_encrypted_header() {
+        let alice = Identity::generate();
+        let bytes = Envelope::from("My secret").encrypt_to(&[alice.recipient()]).unwrap().as_bytes().unwrap();
+
+        // the content type and the compressed flag can't be changed unnoticed
+        for (index, value) in [(6, 1), (5, ENCRYPTED | COMPRESSED)] {
+            let mut changed = bytes.clone();
+            changed[index] = value;
+            let envelope = Envelope::read(&changed).unwrap();
+            assert!(matches!(envelope.decrypt_with(&alice), Err(PngSecretError::DecryptionFailed)));
+        }
+    }
+
+    #[test]
+    fn test_reveal() {
+        let plain = Envelope::from("My secret").compress(Compression::Deflate).unwrap();
+        assert_eq!(plain.key_source(), None);
+        assert_eq!(plain.clone().reveal(None, None, 1000).unwrap().text().unwrap(), "My secret");
+
+        let alice = Identity::generate();
+        let envelope = plain.encrypt_to(&[alice.recipient()]).unwrap();
+        assert_eq!(envelope.key_source(), Some(KeySource::Recipients));
+
+        let key = Key::Passphrase("not this one".to_string());
+        assert!(matches!(envelope.clone().reveal(Some(&key), None, 1000), Err(PngSecretError::KeyNeeded)));
+        assert!(matches!(
+            envelope.clone().reveal(None, Some(&Identity::generate()), 1000),
+            Err(PngSecretError::DecryptionFailed)
+        ));
+        let revealed = envelope.reveal(None, Some(&alice), 1000).unwrap();
+        assert_eq!(revealed.text().unwrap(), "My secret");
+    }
+}
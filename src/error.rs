@@ -0,0 +1,245 @@
+use std::fmt::Display;
+use std::string::FromUtf8Error;
+
+use crate::chunk_type::ChunkType;
+use crate::validate::Violation;
+
+/// Every failure returned by the png-secret library.
+#[derive(Debug)]
+pub enum PngSecretError {
+    /// Reading or writing the underlying file failed.
+    Io(std::io::Error),
+
+    /// The first 8 bytes are not the PNG signature.
+    InvalidSignature,
+
+    /// The CRC stored after a chunk doesn't match its content.
+    ///
+    /// `expected` is the CRC computed from the chunk type and data, `found` is
+    /// the one read from the file and `offset` is where the chunk starts.
+    CrcMismatch {
+        chunk_type: ChunkType,
+        expected: u32,
+        found: u32,
+        offset: usize,
+    },
+
+    /// The input ends in the middle of the chunk starting at `offset`.
+    TruncatedChunk { offset: usize },
+
+    /// The value can't be used as a chunk type.
+    InvalidChunkType(String),
+
+    /// No chunk of this type exists in the png.
+    ChunkNotFound(String),
+
+    /// There is no position `usize` in the chunk list.
+    InvalidIndex(usize),
+
+    /// A chunk was written after `IEND`.
+    ChunkAfterIend(ChunkType),
+
+    /// The data of a known chunk doesn't follow the spec.
+    InvalidChunkData { chunk_type: String, reason: String },
+
+    /// The png breaks the chunk ordering rules of the spec.
+    InvalidStructure(Vec<Violation>),
+
+    /// The chunk data isn't valid UTF-8.
+    InvalidUtf8(FromUtf8Error),
+
+    /// The compressed data isn't a valid zlib stream.
+    InvalidZlib(String),
+
+    /// The decompressed data would be bigger than the limit, in bytes.
+    DecompressionLimit(usize),
+
+    /// The image data doesn't match the image header.
+    InvalidImage(String),
+
+    /// The options can't be used to hide data in the pixels of this image.
+    InvalidLsbOptions(String),
+
+    /// The message is `length` bytes long but only `capacity` bytes fit.
+    MessageTooLong { length: usize, capacity: usize },
+
+    /// No message was found in the pixels with the given lsb key.
+    WrongLsbKey,
+
+    /// The encrypted payload is malformed.
+    InvalidEncryptedData(String),
+
+    /// The key is wrong or the encrypted payload was modified.
+    DecryptionFailed,
+
+    /// The value isn't a valid public key or identity.
+    InvalidKey(String),
+
+    /// The secret envelope header is malformed or doesn't match its payload.
+    InvalidEnvelope(String),
+
+    /// The payload is too long for the u32 length of an envelope.
+    PayloadTooLarge(usize),
+
+    /// The secret is encrypted and must be decrypted before it is read.
+    KeyNeeded,
+
+    /// Parts of a split secret, numbered from 0, can't be found.
+    MissingParts { missing: Vec<u16>, total: u16 },
+
+    /// A chunk of this type is already in the png.
+    ChunkExists(String),
+}
+
+impl PngSecretError {
+    // errors from `Chunk::try_from` use offsets relative to the chunk,
+    // move them to the position of the chunk in the whole file
+    pub(crate) fn at_offset(self, chunk_offset: usize) -> PngSecretError {
+        match self {
+            PngSecretError::CrcMismatch { chunk_type, expected, found, offset } => {
+                PngSecretError::CrcMismatch {
+                    chunk_type,
+                    expected,
+                    found,
+                    offset: offset + chunk_offset,
+                }
+            }
+            PngSecretError::TruncatedChunk { offset } => PngSecretError::TruncatedChunk {
+                offset: offset + chunk_offset,
+            },
+            error => error,
+        }
+    }
+}
+
+impl Display for PngSecretError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PngSecretError::Io(e) => write!(f, "{}", e),
+            PngSecretError::InvalidSignature => write!(f, "Invalid header"),
+            PngSecretError::CrcMismatch { chunk_type, expected, found, offset } => write!(
+                f,
+                "CRC not valid for chunk '{}' at offset {} (expected {:#010x}, found {:#010x})",
+                chunk_type, offset, expected, found
+            ),
+            PngSecretError::TruncatedChunk { offset } => {
+                write!(f, "Chunk at offset {} is truncated", offset)
+            }
+            PngSecretError::InvalidChunkType(chunk_type) => {
+                write!(f, "'{}' is not a valid chunk type", chunk_type)
+            }
+            PngSecretError::ChunkNotFound(chunk_type) => {
+                write!(f, "Chunk '{}' doesn't exist", chunk_type)
+            }
+            PngSecretError::InvalidIndex(index) => {
+                write!(f, "There is no chunk position {}", index)
+            }
+            PngSecretError::ChunkExists(chunk_type) => {
+                write!(f, "Chunk '{}' already exists", chunk_type)
+            }
+            PngSecretError::ChunkAfterIend(chunk_type) => {
+                write!(f, "Chunk '{}' can't be written after IEND", chunk_type)
+            }
+            PngSecretError::InvalidChunkData { chunk_type, reason } => {
+                write!(f, "Invalid {} chunk : {}", chunk_type, reason)
+            }
+            PngSecretError::InvalidStructure(violations) => {
+                write!(f, "Invalid png structure")?;
+                for violation in violations {
+                    write!(f, "\n  {}", violation)?;
+                }
+                Ok(())
+            }
+            PngSecretError::InvalidUtf8(e) => write!(f, "{}", e),
+            PngSecretError::InvalidZlib(reason) => write!(f, "Invalid zlib data : {}", reason),
+            PngSecretError::DecompressionLimit(limit) => {
+                write!(f, "Decompressed data is bigger than {} bytes", limit)
+            }
+            PngSecretError::InvalidImage(reason) => write!(f, "Invalid image : {}", reason),
+            PngSecretError::InvalidLsbOptions(reason) => write!(f, "{}", reason),
+            PngSecretError::MessageTooLong { length, capacity } => write!(
+                f,
+                "The message is {} bytes long, the image can only hide {} bytes",
+                length, capacity
+            ),
+            PngSecretError::InvalidEncryptedData(reason) => write!(f, "Invalid encrypted data : {}", reason),
+            PngSecretError::WrongLsbKey => {
+                write!(f, "No message found with this lsb key, the key is wrong or there is no message")
+            }
+            PngSecretError::DecryptionFailed => {
+                write!(f, "Decryption failed, the key is wrong or the secret was modified")
+            }
+            PngSecretError::InvalidKey(reason) => write!(f, "Invalid key : {}", reason),
+            PngSecretError::InvalidEnvelope(reason) => write!(f, "Invalid secret envelope : {}", reason),
+            PngSecretError::PayloadTooLarge(length) => {
+                write!(f, "The secret is {} bytes long, an envelope holds at most {} bytes", length, u32::MAX)
+            }
+            PngSecretError::KeyNeeded => write!(f, "The message is encrypted, a key is needed to read it"),
+            PngSecretError::MissingParts { missing, total } => {
+                // counted from 1 for people
+                let missing: Vec<String> = missing.iter().map(|index| (index + 1).to_string()).collect();
+                write!(f, "Parts {} of the {} parts of the secret are missing", missing.join(", "), total)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PngSecretError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PngSecretError::Io(e) => Some(e),
+            PngSecretError::InvalidUtf8(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for PngSecretError {
+    fn from(value: std::io::Error) -> Self {
+        PngSecretError::Io(value)
+    }
+}
+
+impl From<FromUtf8Error> for PngSecretError {
+    fn from(value: FromUtf8Error) -> Self {
+        PngSecretError::InvalidUtf8(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_at_offset() {
+        let error = PngSecretError::TruncatedChunk { offset: 4 }.at_offset(8);
+        assert!(matches!(error, PngSecretError::TruncatedChunk { offset: 12 }));
+
+        let error = PngSecretError::InvalidSignature.at_offset(8);
+        assert!(matches!(error, PngSecretError::InvalidSignature));
+    }
+
+    #[test]
+    fn test_crc_mismatch_message() {
+        let error = PngSecretError::CrcMismatch {
+            chunk_type: ChunkType::from_str("RuSt").unwrap(),
+            expected: 1,
+            found: 2,
+            offset: 33,
+        };
+        assert_eq!(
+            error.to_string(),
+            "CRC not valid for chunk 'RuSt' at offset 33 (expected 0x00000001, found 0x00000002)"
+        );
+    }
+
+    #[test]
+    fn test_error_source() {
+        let utf8_error = String::from_utf8(vec![0xff]).unwrap_err();
+        let error = PngSecretError::from(utf8_error);
+        assert!(error.source().is_some());
+        assert!(PngSecretError::InvalidSignature.source().is_none());
+    }
+}
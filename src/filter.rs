@@ -0,0 +1,215 @@
+//! Scanline filters of the PNG spec, applied to each row before compression.
+
+use crate::zlib;
+use crate::{PngSecretError, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterType {
+    None,
+    Sub,
+    Up,
+    Average,
+    Paeth,
+}
+
+impl FilterType {
+    pub const ALL: [FilterType; 5] = [
+        FilterType::None,
+        FilterType::Sub,
+        FilterType::Up,
+        FilterType::Average,
+        FilterType::Paeth,
+    ];
+
+    pub fn value(&self) -> u8 {
+        match self {
+            FilterType::None => 0,
+            FilterType::Sub => 1,
+            FilterType::Up => 2,
+            FilterType::Average => 3,
+            FilterType::Paeth => 4,
+        }
+    }
+}
+
+impl TryFrom<u8> for FilterType {
+    type Error = PngSecretError;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(FilterType::None),
+            1 => Ok(FilterType::Sub),
+            2 => Ok(FilterType::Up),
+            3 => Ok(FilterType::Average),
+            4 => Ok(FilterType::Paeth),
+            _ => Err(PngSecretError::InvalidImage(format!("unknown filter type {}", value))),
+        }
+    }
+}
+
+/// How the encoder picks the filter of each row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FilterStrategy {
+    /// The same filter for every row.
+    Fixed(FilterType),
+    /// The filter with the smallest sum of the filtered bytes, read as
+    /// signed values. Indexed and low bit depth images stay unfiltered.
+    #[default]
+    MinSumAbsDiff,
+    /// Compress the row with every filter and keep the smallest, slow.
+    BruteForce,
+}
+
+// bytes of the previous filtered rows compressed with each candidate row
+const BRUTE_FORCE_CONTEXT: usize = 4 * 1024;
+
+// predictor of the Paeth filter, the neighbour closest to a + b - c
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// value predicted for `row[i]`, from the bytes `bpp` to the left and above
+fn predict(filter_type: FilterType, bpp: usize, previous: &[u8], row: &[u8], i: usize) -> u8 {
+    let left = if i >= bpp { row[i - bpp] } else { 0 };
+    let up = previous[i];
+    let up_left = if i >= bpp { previous[i - bpp] } else { 0 };
+
+    match filter_type {
+        FilterType::None => 0,
+        FilterType::Sub => left,
+        FilterType::Up => up,
+        FilterType::Average => ((left as u16 + up as u16) / 2) as u8,
+        FilterType::Paeth => paeth(left, up, up_left),
+    }
+}
+
+/// Undo `filter_type` on `row` in place.
+///
+/// `bpp` is the number of bytes in a pixel, at least 1, and `previous` the
+/// unfiltered row above, all zeros for the first row.
+pub fn unfilter(filter_type: FilterType, bpp: usize, previous: &[u8], row: &mut [u8]) {
+    for i in 0..row.len() {
+        row[i] = row[i].wrapping_add(predict(filter_type, bpp, previous, row, i));
+    }
+}
+
+/// Apply `filter_type` to `row` into `output`.
+pub fn filter(filter_type: FilterType, bpp: usize, previous: &[u8], row: &[u8], output: &mut [u8]) {
+    for i in 0..row.len() {
+        output[i] = row[i].wrapping_sub(predict(filter_type, bpp, previous, row, i));
+    }
+}
+
+/// Filter `row` with the filter chosen by `strategy`, and add the filter type
+/// and the filtered bytes to `output`.
+pub(crate) fn filter_row(strategy: FilterStrategy, bpp: usize, previous: &[u8], row: &[u8], output: &mut Vec<u8>) {
+    let mut filtered = vec![0; row.len()];
+    let mut best_by = |cost: &dyn Fn(&[u8]) -> usize| {
+        FilterType::ALL
+            .into_iter()
+            .min_by_key(|filter_type| {
+                filter(*filter_type, bpp, previous, row, &mut filtered);
+                cost(&filtered)
+            })
+            .expect("five filter types")
+    };
+
+    let filter_type = match strategy {
+        FilterStrategy::Fixed(filter_type) => filter_type,
+        FilterStrategy::MinSumAbsDiff => {
+            best_by(&|filtered| filtered.iter().map(|b| (*b as i8).unsigned_abs() as usize).sum())
+        }
+        FilterStrategy::BruteForce => {
+            // compress after the last rows written, matches often come from there
+            let context = &output[output.len().saturating_sub(BRUTE_FORCE_CONTEXT)..];
+            best_by(&|filtered| zlib::compress(&[context, filtered].concat(), 6).len())
+        }
+    };
+
+    filter(filter_type, bpp, previous, row, &mut filtered);
+    output.push(filter_type.value());
+    output.extend(filtered);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_type() {
+        for filter_type in FilterType::ALL {
+            assert_eq!(FilterType::try_from(filter_type.value()).unwrap(), filter_type);
+        }
+        assert!(FilterType::try_from(5).is_err());
+    }
+
+    #[test]
+    fn test_paeth() {
+        assert_eq!(paeth(10, 20, 10), 20);
+        assert_eq!(paeth(20, 10, 10), 20);
+        assert_eq!(paeth(10, 20, 30), 10);
+    }
+
+    #[test]
+    fn test_unfilter() {
+        let previous = [10, 20, 30, 40];
+
+        let mut row = [1, 2, 3, 4];
+        unfilter(FilterType::Sub, 2, &previous, &mut row);
+        assert_eq!(row, [1, 2, 4, 6]);
+
+        let mut row = [1, 2, 3, 4];
+        unfilter(FilterType::Up, 2, &previous, &mut row);
+        assert_eq!(row, [11, 22, 33, 44]);
+
+        let mut row = [1, 2, 3, 4];
+        unfilter(FilterType::Average, 2, &previous, &mut row);
+        assert_eq!(row, [6, 12, 3 + (6 + 30) / 2, 4 + (12 + 40) / 2]);
+    }
+
+    #[test]
+    fn test_filter_round_trip() {
+        let previous: Vec<u8> = (0..30).map(|i| i * 7).collect();
+        let row: Vec<u8> = (0..30).map(|i| 255 - i * 3).collect();
+
+        for filter_type in FilterType::ALL {
+            let mut filtered = vec![0; row.len()];
+            filter(filter_type, 3, &previous, &row, &mut filtered);
+            unfilter(filter_type, 3, &previous, &mut filtered);
+            assert_eq!(filtered, row);
+        }
+    }
+
+    #[test]
+    fn test_filter_row() {
+        let previous = [0; 6];
+        let row = [10, 20, 30, 40, 50, 60];
+
+        let mut output = Vec::new();
+        filter_row(FilterStrategy::Fixed(FilterType::Up), 1, &previous, &row, &mut output);
+        assert_eq!(output, [2, 10, 20, 30, 40, 50, 60]);
+
+        // a gradient is best predicted from the left
+        let mut output = Vec::new();
+        filter_row(FilterStrategy::MinSumAbsDiff, 1, &previous, &row, &mut output);
+        assert_eq!(output, [1, 10, 10, 10, 10, 10, 10]);
+
+        for strategy in [FilterStrategy::MinSumAbsDiff, FilterStrategy::BruteForce] {
+            let mut output = Vec::new();
+            filter_row(strategy, 1, &previous, &row, &mut output);
+            let mut unfiltered = output[1..].to_vec();
+            unfilter(FilterType::try_from(output[0]).unwrap(), 1, &previous, &mut unfiltered);
+            assert_eq!(unfiltered, row);
+        }
+    }
+}
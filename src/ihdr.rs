@@ -0,0 +1,304 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::{PngSecretError, Result};
+
+/// How the pixels of an image are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorType {
+    Grayscale,
+    Truecolor,
+    Indexed,
+    GrayscaleAlpha,
+    TruecolorAlpha,
+}
+
+impl ColorType {
+    /// Number of samples in a pixel.
+    pub fn channels(&self) -> u8 {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Truecolor => 3,
+            ColorType::TruecolorAlpha => 4,
+        }
+    }
+
+    pub fn allowed_bit_depths(&self) -> &'static [u8] {
+        match self {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            ColorType::Truecolor | ColorType::GrayscaleAlpha | ColorType::TruecolorAlpha => &[8, 16],
+        }
+    }
+
+    pub fn value(&self) -> u8 {
+        match self {
+            ColorType::Grayscale => 0,
+            ColorType::Truecolor => 2,
+            ColorType::Indexed => 3,
+            ColorType::GrayscaleAlpha => 4,
+            ColorType::TruecolorAlpha => 6,
+        }
+    }
+}
+
+impl TryFrom<u8> for ColorType {
+    type Error = PngSecretError;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(ColorType::Grayscale),
+            2 => Ok(ColorType::Truecolor),
+            3 => Ok(ColorType::Indexed),
+            4 => Ok(ColorType::GrayscaleAlpha),
+            6 => Ok(ColorType::TruecolorAlpha),
+            _ => Err(invalid(format!("unknown color type {}", value))),
+        }
+    }
+}
+
+impl Display for ColorType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ColorType::Grayscale => "grayscale",
+            ColorType::Truecolor => "truecolor",
+            ColorType::Indexed => "indexed",
+            ColorType::GrayscaleAlpha => "grayscale with alpha",
+            ColorType::TruecolorAlpha => "truecolor with alpha",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterlaceMethod {
+    None,
+    Adam7,
+}
+
+/// The image header, data of the `IHDR` chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ihdr {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: ColorType,
+    interlace_method: InterlaceMethod,
+}
+
+impl Ihdr {
+    // width and height are limited to 2^31 - 1
+    const MAX_SIZE: u32 = i32::MAX as u32;
+
+    pub fn new(
+        width: u32,
+        height: u32,
+        bit_depth: u8,
+        color_type: ColorType,
+        interlace_method: InterlaceMethod,
+    ) -> Result<Ihdr> {
+        if width == 0 || width > Ihdr::MAX_SIZE || height == 0 || height > Ihdr::MAX_SIZE {
+            return Err(invalid(format!("invalid image size {}x{}", width, height)));
+        }
+        if !color_type.allowed_bit_depths().contains(&bit_depth) {
+            return Err(invalid(format!("bit depth {} not allowed for {} images", bit_depth, color_type)));
+        }
+
+        Ok(Ihdr {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            interlace_method,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+
+    pub fn color_type(&self) -> ColorType {
+        self.color_type
+    }
+
+    /// Always 0, deflate is the only compression method.
+    pub fn compression_method(&self) -> u8 {
+        0
+    }
+
+    /// Always 0, adaptive filtering is the only filter method.
+    pub fn filter_method(&self) -> u8 {
+        0
+    }
+
+    pub fn interlace_method(&self) -> InterlaceMethod {
+        self.interlace_method
+    }
+}
+
+impl TryFrom<&Chunk> for Ihdr {
+    type Error = PngSecretError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        if chunk.chunk_type().to_string() != "IHDR" {
+            return Err(invalid(format!("expected IHDR, found {}", chunk.chunk_type())));
+        }
+
+        let data = chunk.data();
+        if data.len() != 13 {
+            return Err(invalid(format!("data must be 13 bytes, found {}", data.len())));
+        }
+
+        let width = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+        let height = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+        let bit_depth = data[8];
+        let color_type = ColorType::try_from(data[9])?;
+
+        if data[10] != 0 {
+            return Err(invalid(format!("unknown compression method {}", data[10])));
+        }
+        if data[11] != 0 {
+            return Err(invalid(format!("unknown filter method {}", data[11])));
+        }
+        let interlace_method = match data[12] {
+            0 => InterlaceMethod::None,
+            1 => InterlaceMethod::Adam7,
+            method => return Err(invalid(format!("unknown interlace method {}", method))),
+        };
+
+        Ihdr::new(width, height, bit_depth, color_type, interlace_method)
+    }
+}
+
+impl From<Ihdr> for Chunk {
+    fn from(ihdr: Ihdr) -> Self {
+        let mut data = Vec::with_capacity(13);
+        data.extend(ihdr.width.to_be_bytes());
+        data.extend(ihdr.height.to_be_bytes());
+        data.push(ihdr.bit_depth);
+        data.push(ihdr.color_type.value());
+        data.push(ihdr.compression_method());
+        data.push(ihdr.filter_method());
+        data.push(match ihdr.interlace_method {
+            InterlaceMethod::None => 0,
+            InterlaceMethod::Adam7 => 1,
+        });
+
+        // "IHDR" is always a valid chunk type
+        let chunk_type = ChunkType::from_str("IHDR").expect("valid chunk type");
+        Chunk::new(chunk_type, data)
+    }
+}
+
+impl Display for Ihdr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}, {} bit {}", self.width, self.height, self.bit_depth, self.color_type)?;
+        if self.interlace_method == InterlaceMethod::Adam7 {
+            write!(f, ", interlaced")?;
+        }
+        Ok(())
+    }
+}
+
+fn invalid(reason: String) -> PngSecretError {
+    PngSecretError::InvalidChunkData {
+        chunk_type: "IHDR".to_string(),
+        reason,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ihdr_chunk(data: [u8; 13]) -> Chunk {
+        Chunk::new(ChunkType::from_str("IHDR").unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_ihdr_from_chunk() {
+        let chunk = ihdr_chunk([0, 0, 0, 50, 0, 0, 1, 0, 8, 6, 0, 0, 0]);
+        let ihdr = Ihdr::try_from(&chunk).unwrap();
+
+        assert_eq!(ihdr.width(), 50);
+        assert_eq!(ihdr.height(), 256);
+        assert_eq!(ihdr.bit_depth(), 8);
+        assert_eq!(ihdr.color_type(), ColorType::TruecolorAlpha);
+        assert_eq!(ihdr.color_type().channels(), 4);
+        assert_eq!(ihdr.compression_method(), 0);
+        assert_eq!(ihdr.filter_method(), 0);
+        assert_eq!(ihdr.interlace_method(), InterlaceMethod::None);
+    }
+
+    #[test]
+    fn test_ihdr_to_chunk() {
+        let chunk = ihdr_chunk([0, 0, 0, 50, 0, 0, 1, 0, 4, 3, 0, 0, 1]);
+        let ihdr = Ihdr::try_from(&chunk).unwrap();
+        assert_eq!(Chunk::from(ihdr), chunk);
+    }
+
+    #[test]
+    fn test_invalid_bit_depth() {
+        let chunk = ihdr_chunk([0, 0, 0, 50, 0, 0, 0, 50, 4, 2, 0, 0, 0]);
+        assert!(matches!(Ihdr::try_from(&chunk), Err(PngSecretError::InvalidChunkData { .. })));
+
+        let chunk = ihdr_chunk([0, 0, 0, 50, 0, 0, 0, 50, 16, 3, 0, 0, 0]);
+        assert!(Ihdr::try_from(&chunk).is_err());
+    }
+
+    #[test]
+    fn test_invalid_color_type() {
+        let chunk = ihdr_chunk([0, 0, 0, 50, 0, 0, 0, 50, 8, 5, 0, 0, 0]);
+        assert!(Ihdr::try_from(&chunk).is_err());
+    }
+
+    #[test]
+    fn test_invalid_size() {
+        let chunk = ihdr_chunk([0, 0, 0, 0, 0, 0, 0, 50, 8, 6, 0, 0, 0]);
+        assert!(Ihdr::try_from(&chunk).is_err());
+
+        let chunk = ihdr_chunk([128, 0, 0, 0, 0, 0, 0, 50, 8, 6, 0, 0, 0]);
+        assert!(Ihdr::try_from(&chunk).is_err());
+    }
+
+    #[test]
+    fn test_invalid_methods() {
+        let chunk = ihdr_chunk([0, 0, 0, 50, 0, 0, 0, 50, 8, 6, 1, 0, 0]);
+        assert!(Ihdr::try_from(&chunk).is_err());
+
+        let chunk = ihdr_chunk([0, 0, 0, 50, 0, 0, 0, 50, 8, 6, 0, 1, 0]);
+        assert!(Ihdr::try_from(&chunk).is_err());
+
+        let chunk = ihdr_chunk([0, 0, 0, 50, 0, 0, 0, 50, 8, 6, 0, 0, 2]);
+        assert!(Ihdr::try_from(&chunk).is_err());
+    }
+
+    #[test]
+    fn test_invalid_length() {
+        let chunk = Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0, 0, 0, 50]);
+        assert!(Ihdr::try_from(&chunk).is_err());
+    }
+
+    #[test]
+    fn test_not_ihdr() {
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), vec![0, 0, 0, 50, 0, 0, 0, 50, 8, 6, 0, 0, 0]);
+        assert!(Ihdr::try_from(&chunk).is_err());
+    }
+
+    #[test]
+    fn test_ihdr_display() {
+        let ihdr = Ihdr::new(50, 20, 8, ColorType::Truecolor, InterlaceMethod::Adam7).unwrap();
+        assert_eq!(ihdr.to_string(), "50x20, 8 bit truecolor, interlaced");
+    }
+}
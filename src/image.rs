@@ -0,0 +1,387 @@
+//! Pixels of a png, decoded from its `IDAT` chunks.
+
+use std::io::{self, Read};
+
+use crate::adam7;
+use crate::filter::{self, FilterStrategy, FilterType};
+use crate::ihdr::{ColorType, Ihdr, InterlaceMethod};
+use crate::zlib::{self, ZlibDecoder};
+use crate::{PngSecretError, Result};
+
+/// Biggest image decoded, in bytes of pixels. The size comes from the
+/// header, bigger images are refused before anything is allocated.
+pub const MAX_IMAGE_BYTES: usize = 512 * 1024 * 1024;
+
+/// The unfiltered pixels of an image.
+///
+/// Rows go from top to bottom, each [`Image::stride`] bytes long. Pixels hold
+/// their samples in the order of the color type (gray, RGB, gray and alpha,
+/// RGBA or a palette index). 16 bit samples are big endian, and samples of
+/// less than 8 bits are packed from the most significant bit, the last byte
+/// of a row padded with zeros.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    ihdr: Ihdr,
+    data: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(ihdr: Ihdr, data: Vec<u8>) -> Result<Image> {
+        let expected = image_bytes(&ihdr)?;
+        if data.len() != expected {
+            return Err(PngSecretError::InvalidImage(format!(
+                "expected {} bytes of pixels, found {}",
+                expected,
+                data.len()
+            )));
+        }
+        Ok(Image { ihdr, data })
+    }
+
+    pub fn ihdr(&self) -> Ihdr {
+        self.ihdr
+    }
+
+    /// Choose how the image is stored once encoded, the pixels don't change.
+    pub fn set_interlace_method(&mut self, interlace_method: InterlaceMethod) {
+        let ihdr = &self.ihdr;
+        self.ihdr = Ihdr::new(ihdr.width(), ihdr.height(), ihdr.bit_depth(), ihdr.color_type(), interlace_method)
+            .expect("valid image header");
+    }
+
+    pub fn width(&self) -> u32 {
+        self.ihdr.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.ihdr.height()
+    }
+
+    /// Number of bytes in a row.
+    pub fn stride(&self) -> usize {
+        row_bytes(self.ihdr.width(), bits_per_pixel(&self.ihdr))
+    }
+
+    pub fn row(&self, y: u32) -> &[u8] {
+        let stride = self.stride();
+        &self.data[y as usize * stride..(y as usize + 1) * stride]
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Value of the sample `channel` of pixel (`x`, `y`).
+    pub fn sample(&self, x: u32, y: u32, channel: usize) -> u16 {
+        let bit_depth = self.ihdr.bit_depth() as usize;
+        let (index, shift) = self.sample_position(x, y, channel);
+        match bit_depth {
+            16 => u16::from_be_bytes([self.data[index], self.data[index + 1]]),
+            _ => ((self.data[index] >> shift) & sample_mask(bit_depth)) as u16,
+        }
+    }
+
+    /// Change the sample `channel` of pixel (`x`, `y`), `value` must fit in
+    /// the bit depth.
+    pub fn set_sample(&mut self, x: u32, y: u32, channel: usize, value: u16) {
+        let bit_depth = self.ihdr.bit_depth() as usize;
+        let (index, shift) = self.sample_position(x, y, channel);
+        match bit_depth {
+            16 => self.data[index..index + 2].copy_from_slice(&value.to_be_bytes()),
+            _ => {
+                let mask = sample_mask(bit_depth) << shift;
+                self.data[index] = (self.data[index] & !mask) | (((value as u8) << shift) & mask);
+            }
+        }
+    }
+
+    // byte of the sample, and its shift inside the byte for small bit depths
+    fn sample_position(&self, x: u32, y: u32, channel: usize) -> (usize, usize) {
+        assert!(x < self.width() && y < self.height() && channel < self.ihdr.color_type().channels() as usize);
+
+        let bit_depth = self.ihdr.bit_depth() as usize;
+        let bit = x as usize * bits_per_pixel(&self.ihdr) + channel * bit_depth;
+        let index = y as usize * self.stride() + bit / 8;
+        let shift = if bit_depth < 8 { 8 - bit_depth - bit % 8 } else { 0 };
+        (index, shift)
+    }
+}
+
+fn sample_mask(bit_depth: usize) -> u8 {
+    ((1u16 << bit_depth) - 1) as u8
+}
+
+pub(crate) fn bits_per_pixel(ihdr: &Ihdr) -> usize {
+    ihdr.color_type().channels() as usize * ihdr.bit_depth() as usize
+}
+
+pub(crate) fn row_bytes(width: u32, bits_per_pixel: usize) -> usize {
+    (width as usize * bits_per_pixel).div_ceil(8)
+}
+
+// bytes between a byte and the same byte of the previous pixel, for filters
+pub(crate) fn filter_bpp(ihdr: &Ihdr) -> usize {
+    bits_per_pixel(ihdr).div_ceil(8)
+}
+
+fn image_bytes(ihdr: &Ihdr) -> Result<usize> {
+    let too_big = || PngSecretError::InvalidImage(format!("image is too big, the limit is {} bytes", MAX_IMAGE_BYTES));
+    row_bytes(ihdr.width(), bits_per_pixel(ihdr))
+        .checked_mul(ihdr.height() as usize)
+        .filter(|bytes| *bytes <= MAX_IMAGE_BYTES)
+        .ok_or_else(too_big)
+}
+
+// bytes of the filtered rows, each one starting with its filter type
+fn filtered_bytes(ihdr: &Ihdr) -> usize {
+    let passes = match ihdr.interlace_method() {
+        InterlaceMethod::None => vec![(ihdr.width(), ihdr.height())],
+        InterlaceMethod::Adam7 => adam7::pass_sizes(ihdr.width(), ihdr.height()).to_vec(),
+    };
+    passes
+        .into_iter()
+        .filter(|(width, _)| *width > 0)
+        .map(|(width, height)| (row_bytes(width, bits_per_pixel(ihdr)) + 1) * height as usize)
+        .sum()
+}
+
+/// Decode the pixels from the concatenated `IDAT` data.
+pub(crate) fn decode<R: Read>(ihdr: Ihdr, idat: R) -> Result<Image> {
+    image_bytes(&ihdr)?;
+
+    let mut reader = ZlibDecoder::new(idat, filtered_bytes(&ihdr));
+    let data = match ihdr.interlace_method() {
+        InterlaceMethod::None => {
+            let mut data = Vec::new();
+            read_rows(&mut reader, &ihdr, ihdr.width(), ihdr.height(), &mut data)?;
+            data
+        }
+        InterlaceMethod::Adam7 => {
+            let mut passes = Vec::new();
+            for (width, height) in adam7::pass_sizes(ihdr.width(), ihdr.height()) {
+                // empty passes have no rows at all
+                let mut pass = Vec::new();
+                if width > 0 {
+                    read_rows(&mut reader, &ihdr, width, height, &mut pass)?;
+                }
+                passes.push(pass);
+            }
+            adam7::deinterlace(&ihdr, &passes)
+        }
+    };
+    Image::new(ihdr, data)
+}
+
+/// Filter and compress the pixels into `IDAT` data, interlaced if the
+/// header says so.
+pub(crate) fn encode(image: &Image, strategy: FilterStrategy) -> Vec<u8> {
+    let ihdr = image.ihdr();
+    let strategy = match strategy {
+        FilterStrategy::MinSumAbsDiff if ihdr.bit_depth() < 8 || ihdr.color_type() == ColorType::Indexed => {
+            FilterStrategy::Fixed(FilterType::None)
+        }
+        strategy => strategy,
+    };
+
+    let mut raw = Vec::new();
+    match ihdr.interlace_method() {
+        InterlaceMethod::None => write_rows(&mut raw, &ihdr, ihdr.width(), image.data(), strategy),
+        InterlaceMethod::Adam7 => {
+            let passes = adam7::interlace(&ihdr, image.data());
+            for (pass, (width, _)) in passes.iter().zip(adam7::pass_sizes(ihdr.width(), ihdr.height())) {
+                if width > 0 {
+                    write_rows(&mut raw, &ihdr, width, pass, strategy);
+                }
+            }
+        }
+    }
+    zlib::compress(&raw, 6)
+}
+
+// filter the rows of `width` pixels in `data` into `raw`
+fn write_rows(raw: &mut Vec<u8>, ihdr: &Ihdr, width: u32, data: &[u8], strategy: FilterStrategy) {
+    let stride = row_bytes(width, bits_per_pixel(ihdr));
+    let bpp = filter_bpp(ihdr);
+
+    let mut previous: &[u8] = &vec![0; stride];
+    for row in data.chunks(stride) {
+        filter::filter_row(strategy, bpp, previous, row, raw);
+        previous = row;
+    }
+}
+
+// read and unfilter `height` rows of `width` pixels at the end of `output`
+fn read_rows<R: Read>(reader: &mut R, ihdr: &Ihdr, width: u32, height: u32, output: &mut Vec<u8>) -> Result<()> {
+    let stride = row_bytes(width, bits_per_pixel(ihdr));
+    let bpp = filter_bpp(ihdr);
+
+    let mut previous = vec![0; stride];
+    let mut row = vec![0; stride + 1];
+    for _ in 0..height {
+        reader.read_exact(&mut row).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => PngSecretError::InvalidImage("image data is too short".to_string()),
+            _ => zlib::from_io_error(e),
+        })?;
+
+        let filter_type = FilterType::try_from(row[0])?;
+        filter::unfilter(filter_type, bpp, &previous, &mut row[1..]);
+        output.extend(&row[1..]);
+        previous.copy_from_slice(&row[1..]);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ihdr(width: u32, height: u32, bit_depth: u8, color_type: ColorType) -> Ihdr {
+        Ihdr::new(width, height, bit_depth, color_type, InterlaceMethod::None).unwrap()
+    }
+
+    // filter every row with `filter_type` and compress
+    fn idat(ihdr: &Ihdr, data: &[u8], filter_type: FilterType) -> Vec<u8> {
+        let stride = row_bytes(ihdr.width(), bits_per_pixel(ihdr));
+        let mut raw = Vec::new();
+        let mut previous = vec![0; stride];
+        for row in data.chunks(stride) {
+            let mut filtered = vec![0; stride];
+            filter::filter(filter_type, filter_bpp(ihdr), &previous, row, &mut filtered);
+            raw.push(filter_type.value());
+            raw.extend(filtered);
+            previous.copy_from_slice(row);
+        }
+        zlib::compress(&raw, 6)
+    }
+
+    #[test]
+    fn test_decode() {
+        let ihdr = ihdr(3, 2, 8, ColorType::Truecolor);
+        let data: Vec<u8> = (0..18).map(|i| i * 13).collect();
+
+        for filter_type in FilterType::ALL {
+            let image = decode(ihdr, idat(&ihdr, &data, filter_type).as_slice()).unwrap();
+            assert_eq!(image.data(), data);
+            assert_eq!(image.stride(), 9);
+            assert_eq!(image.sample(1, 1, 2), 14 * 13);
+        }
+    }
+
+    #[test]
+    fn test_decode_interlaced() {
+        let ihdr = Ihdr::new(11, 6, 4, ColorType::Grayscale, InterlaceMethod::Adam7).unwrap();
+        let data: Vec<u8> = (0..36u8).map(|i| if i % 6 == 5 { i & 0xf0 } else { i * 7 }).collect();
+
+        let image = Image::new(ihdr, data).unwrap();
+
+        let decoded = decode(ihdr, encode(&image, FilterStrategy::default()).as_slice()).unwrap();
+        assert_eq!(decoded, image);
+        assert_eq!(decoded.ihdr().interlace_method(), InterlaceMethod::Adam7);
+    }
+
+    #[test]
+    fn test_set_interlace_method() {
+        let mut image = Image::new(ihdr(1, 1, 8, ColorType::Grayscale), vec![7]).unwrap();
+        image.set_interlace_method(InterlaceMethod::Adam7);
+        assert_eq!(image.ihdr().interlace_method(), InterlaceMethod::Adam7);
+        assert_eq!(image.data(), [7]);
+    }
+
+    #[test]
+    fn test_encode() {
+        let ihdr = ihdr(3, 2, 8, ColorType::Truecolor);
+        let image = Image::new(ihdr, (0..18).map(|i| i * 5).collect()).unwrap();
+
+        for strategy in [
+            FilterStrategy::Fixed(FilterType::Paeth),
+            FilterStrategy::MinSumAbsDiff,
+            FilterStrategy::BruteForce,
+        ] {
+            assert_eq!(decode(ihdr, encode(&image, strategy).as_slice()).unwrap(), image);
+        }
+    }
+
+    #[test]
+    fn test_decode_too_short() {
+        let ihdr = ihdr(3, 2, 8, ColorType::Truecolor);
+        let data = [0; 9];
+        let idat = zlib::compress(&[&[0], &data[..]].concat(), 6);
+
+        assert!(matches!(decode(ihdr, idat.as_slice()), Err(PngSecretError::InvalidImage(_))));
+    }
+
+    #[test]
+    fn test_decode_too_big() {
+        // 2^31 - 1 pixels of 8 bytes, refused before allocating anything
+        let ihdr = ihdr(i32::MAX as u32, 1, 16, ColorType::TruecolorAlpha);
+        let idat = zlib::compress(&[0; 16], 6);
+        assert!(matches!(decode(ihdr, idat.as_slice()), Err(PngSecretError::InvalidImage(_))));
+        assert!(Image::new(ihdr, vec![]).is_err());
+    }
+
+    #[test]
+    fn test_decode_too_long() {
+        let ihdr = ihdr(3, 2, 8, ColorType::Truecolor);
+        let raw = vec![0; 2 * 10 + 100_000];
+        let idat = zlib::compress(&raw, 6);
+        assert!(matches!(decode(ihdr, idat.as_slice()), Err(PngSecretError::DecompressionLimit(20))));
+        assert_eq!(filtered_bytes(&ihdr), 20);
+
+        let interlaced = Ihdr::new(3, 2, 8, ColorType::Truecolor, InterlaceMethod::Adam7).unwrap();
+        // passes of 1x1, 1x1, 1x1 and 3x1 pixels
+        assert_eq!(filtered_bytes(&interlaced), 3 * 4 + 10);
+    }
+
+    #[test]
+    fn test_decode_invalid_filter() {
+        let ihdr = ihdr(1, 1, 8, ColorType::Grayscale);
+        let idat = zlib::compress(&[5, 0], 6);
+        assert!(decode(ihdr, idat.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_samples_16_bit() {
+        let ihdr = ihdr(2, 1, 16, ColorType::GrayscaleAlpha);
+        let mut image = Image::new(ihdr, vec![0, 1, 0, 2, 1, 0, 2, 0]).unwrap();
+        assert_eq!(image.stride(), 8);
+        assert_eq!(image.sample(0, 0, 1), 2);
+        assert_eq!(image.sample(1, 0, 0), 256);
+
+        image.set_sample(1, 0, 1, 0xabcd);
+        assert_eq!(image.data(), [0, 1, 0, 2, 1, 0, 0xab, 0xcd]);
+    }
+
+    #[test]
+    fn test_samples_small_bit_depth() {
+        let ihdr = ihdr(5, 2, 2, ColorType::Indexed);
+        let mut image = Image::new(ihdr, vec![0b00011011, 0b11000000, 0, 0]).unwrap();
+        assert_eq!(image.stride(), 2);
+        assert_eq!(image.sample(1, 0, 0), 1);
+        assert_eq!(image.sample(3, 0, 0), 3);
+        assert_eq!(image.sample(4, 0, 0), 3);
+
+        image.set_sample(2, 1, 0, 2);
+        assert_eq!(image.row(1), [0b00001000, 0]);
+    }
+
+    #[test]
+    fn test_filter_bpp() {
+        assert_eq!(filter_bpp(&ihdr(1, 1, 1, ColorType::Grayscale)), 1);
+        assert_eq!(filter_bpp(&ihdr(1, 1, 8, ColorType::Truecolor)), 3);
+        assert_eq!(filter_bpp(&ihdr(1, 1, 16, ColorType::TruecolorAlpha)), 8);
+    }
+
+    #[test]
+    fn test_invalid_size() {
+        let ihdr = ihdr(3, 2, 8, ColorType::Truecolor);
+        assert!(Image::new(ihdr, vec![0; 17]).is_err());
+    }
+}
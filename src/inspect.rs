@@ -0,0 +1,294 @@
+//! A chunk by chunk report of a png, like `pngcheck -v`.
+//!
+//! Unlike the other readers, a wrong CRC or an invalid chunk type doesn't
+//! stop the inspection, they are reported with the chunk. Only a truncated
+//! chunk ends it.
+
+use crate::ancillary::{Bkgd, Chrm, Gama, Hist, Iccp, Phys, PhysUnit, RenderingIntent, Sbit, Splt, Srgb, Time, Trns};
+use crate::chunk::{self, Chunk};
+use crate::chunk_type::ChunkType;
+use crate::envelope::{ContentType, Envelope};
+use crate::ihdr::{ColorType, Ihdr};
+use crate::png::Png;
+use crate::text::TextChunk;
+use crate::validate::{self, Violation};
+use crate::{PngSecretError, Result};
+
+// longest text shown in the summary of a text chunk
+const TEXT_PREVIEW: usize = 40;
+
+/// What was found about one chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkInfo {
+    /// Position of the chunk in the file, its length field included.
+    pub offset: usize,
+    pub length: u32,
+    /// The chunk type as stored, it may not be a valid one.
+    pub chunk_type: [u8; 4],
+    pub stored_crc: u32,
+    pub computed_crc: u32,
+    /// What the chunk holds, for the chunk types this crate knows.
+    pub summary: Option<String>,
+}
+
+impl ChunkInfo {
+    pub fn chunk_type(&self) -> Result<ChunkType> {
+        ChunkType::try_from(self.chunk_type)
+    }
+
+    pub fn is_crc_valid(&self) -> bool {
+        self.stored_crc == self.computed_crc
+    }
+}
+
+/// Every chunk of a png, and the error that stopped the reading if any.
+#[derive(Debug)]
+pub struct Inspection {
+    pub chunks: Vec<ChunkInfo>,
+    pub error: Option<PngSecretError>,
+}
+
+impl Inspection {
+    /// Number of chunks with a wrong CRC.
+    pub fn crc_errors(&self) -> usize {
+        self.chunks.iter().filter(|chunk| !chunk.is_crc_valid()).count()
+    }
+
+    /// Every chunk ordering rule of the spec broken by the chunks found.
+    pub fn validate(&self) -> Vec<Violation> {
+        let chunk_types: Vec<String> = self.chunks
+            .iter()
+            .map(|chunk| String::from_utf8_lossy(&chunk.chunk_type).into_owned())
+            .collect();
+
+        validate::validate(&chunk_types)
+    }
+}
+
+/// Inspect every chunk of the png in `bytes`, only the signature has to be
+/// valid.
+pub fn inspect(bytes: &[u8]) -> Result<Inspection> {
+    if bytes.get(..8) != Some(&Png::STANDARD_HEADER[..]) {
+        return Err(PngSecretError::InvalidSignature);
+    }
+
+    let mut chunks = Vec::new();
+    let mut color_type = None;
+    let mut offset = Png::STANDARD_HEADER.len();
+
+    while offset < bytes.len() {
+        let rest = &bytes[offset..];
+        // the length comes from the input, check it before slicing
+        let chunk_length = rest
+            .get(..4)
+            .map(|length| u32::from_be_bytes(length.try_into().unwrap()) as usize)
+            .and_then(|length| length.checked_add(12))
+            .filter(|chunk_length| *chunk_length <= rest.len());
+        let Some(chunk_length) = chunk_length else {
+            return Ok(Inspection {
+                chunks,
+                error: Some(PngSecretError::TruncatedChunk { offset }),
+            });
+        };
+
+        let chunk_type: [u8; 4] = rest[4..8].try_into().unwrap();
+        let data = &rest[8..chunk_length - 4];
+        let stored_crc = u32::from_be_bytes(rest[chunk_length - 4..chunk_length].try_into().unwrap());
+
+        // chunks with an invalid chunk type have no summary
+        let chunk = ChunkType::try_from(chunk_type).ok().map(|chunk_type| Chunk::new(chunk_type, data.to_vec()));
+        if let Some(ihdr) = chunk.as_ref().and_then(|chunk| Ihdr::try_from(chunk).ok()) {
+            color_type = Some(ihdr.color_type());
+        }
+        let summary = chunk.and_then(|chunk| summary(&chunk, color_type));
+
+        chunks.push(ChunkInfo {
+            offset,
+            length: data.len() as u32,
+            chunk_type,
+            stored_crc,
+            computed_crc: chunk::checksum(&chunk_type, data),
+            summary,
+        });
+        offset += chunk_length;
+    }
+
+    Ok(Inspection { chunks, error: None })
+}
+
+// a short description of the data of `chunk`, `tRNS` needs the color type
+fn summary(chunk: &Chunk, color_type: Option<ColorType>) -> Option<String> {
+    let summary = match &chunk.chunk_type().bytes() {
+        b"IHDR" => Ihdr::try_from(chunk).map(|ihdr| ihdr.to_string()),
+        b"PLTE" => Ok(format!("{} palette entries", chunk.data().len() / 3)),
+        b"IDAT" => Ok("image data".to_string()),
+        b"IEND" => Ok("end of image".to_string()),
+        b"gAMA" => Gama::try_from(chunk).map(|gama| format!("gamma {:.5}", gama.value())),
+        b"cHRM" => Chrm::try_from(chunk).map(|chrm| {
+            let point = |x: u32, y: u32| format!("({:.4}, {:.4})", x as f64 / 100000.0, y as f64 / 100000.0);
+            format!(
+                "white {}, red {}, green {}, blue {}",
+                point(chrm.white_x, chrm.white_y),
+                point(chrm.red_x, chrm.red_y),
+                point(chrm.green_x, chrm.green_y),
+                point(chrm.blue_x, chrm.blue_y)
+            )
+        }),
+        b"sRGB" => Srgb::try_from(chunk).map(|srgb| {
+            let intent = match srgb.intent {
+                RenderingIntent::Perceptual => "perceptual",
+                RenderingIntent::RelativeColorimetric => "relative colorimetric",
+                RenderingIntent::Saturation => "saturation",
+                RenderingIntent::AbsoluteColorimetric => "absolute colorimetric",
+            };
+            format!("sRGB, {} rendering intent", intent)
+        }),
+        b"iCCP" => Iccp::try_from(chunk).map(|iccp| format!("ICC profile '{}'", iccp.profile_name)),
+        b"pHYs" => Phys::try_from(chunk).map(|phys| match phys.unit {
+            PhysUnit::Meter => format!("{}x{} pixels per meter", phys.x, phys.y),
+            PhysUnit::Unknown => format!("aspect ratio {}:{}", phys.x, phys.y),
+        }),
+        b"sBIT" => Sbit::try_from(chunk).map(|sbit| {
+            let bits: Vec<String> = sbit.bits.iter().map(u8::to_string).collect();
+            format!("significant bits {}", bits.join(", "))
+        }),
+        b"bKGD" => Bkgd::try_from(chunk).map(|bkgd| match bkgd {
+            Bkgd::Palette(index) => format!("background palette entry {}", index),
+            Bkgd::Gray(gray) => format!("background gray {}", gray),
+            Bkgd::Rgb(r, g, b) => format!("background rgb ({}, {}, {})", r, g, b),
+        }),
+        b"hIST" => Hist::try_from(chunk).map(|hist| format!("{} frequencies", hist.frequencies.len())),
+        b"tRNS" => match color_type {
+            Some(color_type) => Trns::from_chunk(chunk, color_type),
+            None => Trns::try_from(chunk),
+        }
+        .map(|trns| match trns {
+            Trns::Gray(gray) => format!("transparent gray {}", gray),
+            Trns::Rgb(r, g, b) => format!("transparent rgb ({}, {}, {})", r, g, b),
+            Trns::Palette(alphas) => format!("{} palette alphas", alphas.len()),
+        }),
+        b"sPLT" => Splt::try_from(chunk).map(|splt| {
+            format!("palette '{}', {} entries of {} bits", splt.name, splt.entries.len(), splt.sample_depth)
+        }),
+        b"tIME" => Time::try_from(chunk).map(|time| {
+            format!(
+                "modified {:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+                time.year, time.month, time.day, time.hour, time.minute, time.second
+            )
+        }),
+        b"tEXt" | b"zTXt" | b"iTXt" => TextChunk::try_from(chunk).map(|text| match text.text() {
+            Ok(content) => format!("keyword '{}' : \"{}\"", text.keyword(), preview(&content)),
+            Err(e) => format!("keyword '{}', {}", text.keyword(), e),
+        }),
+        _ if Envelope::is_envelope(chunk.data()) => Envelope::try_from(chunk.data()).map(|envelope| secret(&envelope)),
+        // secrets written before envelopes are plain text
+        _ if !chunk.chunk_type().is_standard() => {
+            return std::str::from_utf8(chunk.data()).ok().map(|text| format!("text \"{}\"", preview(text)));
+        }
+        _ => return None,
+    };
+
+    Some(summary.unwrap_or_else(|e| format!("invalid : {}", e)))
+}
+
+fn secret(envelope: &Envelope) -> String {
+    let content = match envelope.content_type {
+        ContentType::Text => "text",
+        ContentType::Binary => "binary",
+        ContentType::File => "file",
+    };
+    let mut summary = format!("secret, {}, {} bytes", content, envelope.payload.len());
+    for (set, flag) in [
+        (envelope.flags.compressed, "compressed"),
+        (envelope.flags.encrypted, "encrypted"),
+        (envelope.flags.split, "split"),
+    ] {
+        if set {
+            summary.push_str(", ");
+            summary.push_str(flag);
+        }
+    }
+    summary
+}
+
+fn preview(text: &str) -> String {
+    match text.char_indices().nth(TEXT_PREVIEW) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    use crate::ihdr::InterlaceMethod;
+    use crate::text::Text;
+
+    fn testing_png() -> Png {
+        let ihdr = Ihdr::new(2, 1, 8, ColorType::Truecolor, InterlaceMethod::None).unwrap();
+        let mut png = Png::from_chunks(vec![
+            ihdr.into(),
+            Gama { gamma: 45455 }.into(),
+            Text::new("Comment", "A cat").unwrap().into(),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), vec![0; 4]),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+        ]);
+        crate::secret::encode(&mut png, "ruSt", "Secret message").unwrap();
+        png
+    }
+
+    #[test]
+    fn test_inspect() {
+        let inspection = inspect(&testing_png().as_bytes()).unwrap();
+        assert!(inspection.error.is_none());
+        assert_eq!(inspection.crc_errors(), 0);
+        assert!(inspection.validate().is_empty());
+
+        let chunks = &inspection.chunks;
+        let types: Vec<&[u8; 4]> = chunks.iter().map(|chunk| &chunk.chunk_type).collect();
+        assert_eq!(types, [b"IHDR", b"gAMA", b"tEXt", b"IDAT", b"ruSt", b"IEND"]);
+        assert_eq!((chunks[0].offset, chunks[0].length), (8, 13));
+        assert_eq!(chunks[1].offset, 8 + 12 + 13);
+
+        assert_eq!(chunks[0].summary.as_deref(), Some("2x1, 8 bit truecolor"));
+        assert_eq!(chunks[1].summary.as_deref(), Some("gamma 0.45455"));
+        assert_eq!(chunks[2].summary.as_deref(), Some("keyword 'Comment' : \"A cat\""));
+        assert_eq!(chunks[4].summary.as_deref(), Some("secret, text, 14 bytes"));
+
+        let mut png = testing_png();
+        png.append_chunk(Chunk::new(ChunkType::from_str("olDr").unwrap(), b"Old message".to_vec()));
+        let inspection = inspect(&png.as_bytes()).unwrap();
+        assert_eq!(inspection.chunks[6].summary.as_deref(), Some("text \"Old message\""));
+    }
+
+    #[test]
+    fn test_inspect_bad_crc() {
+        let mut bytes = testing_png().as_bytes();
+        // last byte of the gAMA CRC
+        bytes[8 + 12 + 13 + 12 + 4 - 1] ^= 1;
+
+        let inspection = inspect(&bytes).unwrap();
+        assert_eq!(inspection.chunks.len(), 6);
+        assert_eq!(inspection.crc_errors(), 1);
+        assert!(!inspection.chunks[1].is_crc_valid());
+        assert_eq!(inspection.chunks[1].stored_crc ^ 1, inspection.chunks[1].computed_crc);
+    }
+
+    #[test]
+    fn test_inspect_truncated() {
+        let bytes = testing_png().as_bytes();
+        let inspection = inspect(&bytes[..bytes.len() - 2]).unwrap();
+        assert_eq!(inspection.chunks.len(), 5);
+        assert!(matches!(inspection.error, Some(PngSecretError::TruncatedChunk { .. })));
+
+        assert!(matches!(inspect(b"not a png"), Err(PngSecretError::InvalidSignature)));
+    }
+
+    #[test]
+    fn test_preview() {
+        assert_eq!(preview("short"), "short");
+        assert_eq!(preview(&"é".repeat(50)), format!("{}...", "é".repeat(40)));
+    }
+}
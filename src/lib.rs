@@ -0,0 +1,45 @@
+mod adam7;
+pub mod ancillary;
+pub mod attachment;
+pub mod chunk;
+pub mod chunk_ref;
+pub mod chunk_type;
+pub mod compression;
+pub mod crypto;
+pub mod envelope;
+pub mod error;
+pub mod filter;
+pub mod ihdr;
+pub mod image;
+pub mod inspect;
+pub mod lsb;
+pub mod png;
+pub mod png_ref;
+pub mod reader;
+pub mod recipient;
+pub mod secret;
+pub mod split;
+pub mod text;
+pub mod validate;
+pub mod writer;
+pub mod zlib;
+
+pub use attachment::Attachment;
+pub use chunk::Chunk;
+pub use chunk_ref::ChunkRef;
+pub use chunk_type::ChunkType;
+pub use envelope::Envelope;
+pub use error::PngSecretError;
+pub use filter::{FilterStrategy, FilterType};
+pub use ihdr::{ColorType, Ihdr, InterlaceMethod};
+pub use image::Image;
+pub use lsb::{Channels, LsbOptions};
+pub use png::Png;
+pub use png_ref::PngRef;
+pub use reader::ChunkReader;
+pub use secret::{decode, encode, list, remove};
+pub use text::{IText, Text, TextChunk, ZText};
+pub use validate::{Severity, Violation};
+pub use writer::PngWriter;
+
+pub type Result<T> = std::result::Result<T, PngSecretError>;
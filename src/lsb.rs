@@ -0,0 +1,425 @@
+//! Data hidden in the least significant bits of the pixels.
+//!
+//! Unlike a chunk, it survives tools dropping ancillary chunks, but not a
+//! lossy conversion. The hidden data starts with a 4 byte tag and the
+//! length of the message, big endian.
+//!
+//! Without a key the bits fill the samples in order. With a key, the samples
+//! are visited in a pseudo-random order only the key holders can rebuild.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use rand_chacha::rand_core::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use sha2::{Digest, Sha256};
+
+use crate::ihdr::ColorType;
+use crate::image::Image;
+use crate::{PngSecretError, Result};
+
+// tag and length
+const HEADER_SIZE: usize = 8;
+
+/// Samples of each pixel carrying hidden bits.
+///
+/// The gray sample of grayscale images is used if any of red, green or blue
+/// is selected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Channels {
+    pub red: bool,
+    pub green: bool,
+    pub blue: bool,
+    pub alpha: bool,
+}
+
+impl Channels {
+    // index of the selected samples in a pixel
+    fn indexes(&self, color_type: ColorType) -> Result<Vec<usize>> {
+        let color = self.red || self.green || self.blue;
+        let indexes = match color_type {
+            ColorType::Grayscale => vec![(0, color)],
+            ColorType::GrayscaleAlpha => vec![(0, color), (1, self.alpha)],
+            ColorType::Truecolor => vec![(0, self.red), (1, self.green), (2, self.blue)],
+            ColorType::TruecolorAlpha => vec![(0, self.red), (1, self.green), (2, self.blue), (3, self.alpha)],
+            ColorType::Indexed => {
+                return Err(invalid("indexed images can't hide data in their pixels".to_string()));
+            }
+        };
+
+        let indexes: Vec<usize> = indexes.into_iter().filter(|(_, used)| *used).map(|(i, _)| i).collect();
+        if indexes.is_empty() {
+            return Err(invalid(format!("{} images have no channel '{}'", color_type, self)));
+        }
+        Ok(indexes)
+    }
+}
+
+impl Default for Channels {
+    fn default() -> Self {
+        Channels {
+            red: true,
+            green: true,
+            blue: true,
+            alpha: false,
+        }
+    }
+}
+
+impl FromStr for Channels {
+    type Err = PngSecretError;
+
+    /// Any of the letters `r`, `g`, `b` and `a`, like `rgb`.
+    fn from_str(s: &str) -> Result<Self> {
+        if s.is_empty() || !s.chars().all(|c| "rgba".contains(c)) {
+            return Err(invalid(format!("'{}' isn't a list of channels among r, g, b and a", s)));
+        }
+        Ok(Channels {
+            red: s.contains('r'),
+            green: s.contains('g'),
+            blue: s.contains('b'),
+            alpha: s.contains('a'),
+        })
+    }
+}
+
+impl Display for Channels {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (used, letter) in [(self.red, 'r'), (self.green, 'g'), (self.blue, 'b'), (self.alpha, 'a')] {
+            if used {
+                write!(f, "{}", letter)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where the bits go, the same options must be used to extract them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LsbOptions {
+    /// Low bits replaced in each sample, from 1 to the bit depth.
+    pub bits_per_channel: u8,
+    pub channels: Channels,
+    /// Seed of the order the samples are visited in, see [`key_from_passphrase`].
+    pub key: Option<[u8; 32]>,
+}
+
+impl Default for LsbOptions {
+    fn default() -> Self {
+        LsbOptions {
+            bits_per_channel: 1,
+            channels: Channels::default(),
+            key: None,
+        }
+    }
+}
+
+/// Key for [`LsbOptions::key`] derived from a passphrase.
+pub fn key_from_passphrase(passphrase: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"png-secret lsb order\0");
+    hasher.update(passphrase.as_bytes());
+    hasher.finalize().into()
+}
+
+// the start of a random permutation of 0..length, drawn as needed with a
+// Fisher-Yates shuffle that only remembers the swapped positions
+struct Shuffle {
+    rng: ChaCha20Rng,
+    length: usize,
+    swapped: HashMap<usize, usize>,
+    order: Vec<usize>,
+}
+
+impl Shuffle {
+    fn new(key: [u8; 32], length: usize) -> Shuffle {
+        Shuffle {
+            rng: ChaCha20Rng::from_seed(key),
+            length,
+            swapped: HashMap::new(),
+            order: Vec::new(),
+        }
+    }
+
+    fn get(&mut self, index: usize) -> usize {
+        while self.order.len() <= index {
+            let i = self.order.len();
+            let j = i + self.below((self.length - i) as u64) as usize;
+
+            let value_i = self.swapped.get(&i).copied().unwrap_or(i);
+            let value_j = self.swapped.get(&j).copied().unwrap_or(j);
+            self.swapped.insert(j, value_i);
+            self.swapped.remove(&i);
+            self.order.push(value_j);
+        }
+        self.order[index]
+    }
+
+    // uniform in 0..n, rejecting the values that would bias the modulo
+    fn below(&mut self, n: u64) -> u64 {
+        let zone = u64::MAX - u64::MAX % n;
+        loop {
+            let value = self.rng.next_u64();
+            if value < zone {
+                return value % n;
+            }
+        }
+    }
+}
+
+// position of every hidden bit in an image
+struct Layout {
+    channels: Vec<usize>,
+    bits_per_channel: usize,
+    width: u32,
+    capacity: usize,
+    shuffle: Option<Shuffle>,
+}
+
+impl Layout {
+    fn new(image: &Image, options: &LsbOptions) -> Result<Layout> {
+        let bit_depth = image.ihdr().bit_depth();
+        if options.bits_per_channel == 0 || options.bits_per_channel > bit_depth {
+            return Err(invalid(format!(
+                "{} bits per channel, must be from 1 to {}",
+                options.bits_per_channel, bit_depth
+            )));
+        }
+
+        let channels = options.channels.indexes(image.ihdr().color_type())?;
+        let bits_per_channel = options.bits_per_channel as usize;
+        let samples = image.width() as usize * image.height() as usize * channels.len();
+        Ok(Layout {
+            channels,
+            bits_per_channel,
+            width: image.width(),
+            capacity: samples * bits_per_channel,
+            shuffle: options.key.map(|key| Shuffle::new(key, samples)),
+        })
+    }
+
+    // (x, y, channel, bit in the sample) of hidden bit `bit`
+    fn position(&mut self, bit: usize) -> (u32, u32, usize, usize) {
+        let slot = bit / self.bits_per_channel;
+        let slot = match &mut self.shuffle {
+            Some(shuffle) => shuffle.get(slot),
+            None => slot,
+        };
+        let shift = self.bits_per_channel - 1 - bit % self.bits_per_channel;
+        let pixel = slot / self.channels.len();
+        let channel = self.channels[slot % self.channels.len()];
+        ((pixel % self.width as usize) as u32, (pixel / self.width as usize) as u32, channel, shift)
+    }
+
+    fn write(&mut self, image: &mut Image, start: usize, bytes: &[u8]) {
+        for (i, byte) in bytes.iter().enumerate() {
+            for j in 0..8 {
+                let (x, y, channel, shift) = self.position(start + i * 8 + j);
+                let bit = ((byte >> (7 - j)) & 1) as u16;
+                let sample = image.sample(x, y, channel);
+                image.set_sample(x, y, channel, (sample & !(1 << shift)) | bit << shift);
+            }
+        }
+    }
+
+    fn read(&mut self, image: &Image, start: usize, length: usize) -> Vec<u8> {
+        (0..length)
+            .map(|i| {
+                (0..8).fold(0u8, |byte, j| {
+                    let (x, y, channel, shift) = self.position(start + i * 8 + j);
+                    byte << 1 | ((image.sample(x, y, channel) >> shift) & 1) as u8
+                })
+            })
+            .collect()
+    }
+}
+
+/// Number of message bytes `image` can hide with `options`.
+pub fn capacity(image: &Image, options: &LsbOptions) -> Result<usize> {
+    Ok((Layout::new(image, options)?.capacity / 8).saturating_sub(HEADER_SIZE))
+}
+
+/// Hide `message` in the pixels of `image`, with `tag` to find it back.
+pub fn embed(image: &mut Image, tag: [u8; 4], message: &[u8], options: &LsbOptions) -> Result<()> {
+    let capacity = capacity(image, options)?;
+    if message.len() > capacity {
+        return Err(PngSecretError::MessageTooLong {
+            length: message.len(),
+            capacity,
+        });
+    }
+
+    let mut layout = Layout::new(image, options)?;
+    let mut header = tag.to_vec();
+    header.extend((message.len() as u32).to_be_bytes());
+    layout.write(image, 0, &header);
+    layout.write(image, HEADER_SIZE * 8, message);
+    Ok(())
+}
+
+/// Read the message hidden with `tag` in `image`.
+///
+/// Returns `Ok(None)` if the pixels don't start with `tag`. With a key the
+/// wrong key looks the same, so it fails with [`PngSecretError::WrongLsbKey`]
+/// instead.
+pub fn extract(image: &Image, tag: [u8; 4], options: &LsbOptions) -> Result<Option<Vec<u8>>> {
+    let not_found = || match options.key {
+        Some(_) => Err(PngSecretError::WrongLsbKey),
+        None => Ok(None),
+    };
+
+    let mut layout = Layout::new(image, options)?;
+    if layout.capacity < HEADER_SIZE * 8 {
+        return not_found();
+    }
+
+    let header = layout.read(image, 0, HEADER_SIZE);
+    let length = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+    if header[..4] != tag || length > capacity(image, options)? {
+        return not_found();
+    }
+
+    Ok(Some(layout.read(image, HEADER_SIZE * 8, length)))
+}
+
+fn invalid(reason: String) -> PngSecretError {
+    PngSecretError::InvalidLsbOptions(reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::{Ihdr, InterlaceMethod};
+
+    fn image(width: u32, height: u32, bit_depth: u8, color_type: ColorType) -> Image {
+        let ihdr = Ihdr::new(width, height, bit_depth, color_type, InterlaceMethod::None).unwrap();
+        let size = (width as usize * color_type.channels() as usize * bit_depth as usize).div_ceil(8) * height as usize;
+        Image::new(ihdr, (0..size).map(|i| (i * 31) as u8).collect()).unwrap()
+    }
+
+    #[test]
+    fn test_embed_extract() {
+        let mut image = image(20, 10, 8, ColorType::TruecolorAlpha);
+        let original = image.clone();
+        let options = LsbOptions::default();
+
+        embed(&mut image, *b"RuSt", b"Secret message", &options).unwrap();
+        assert_eq!(extract(&image, *b"RuSt", &options).unwrap(), Some(b"Secret message".to_vec()));
+
+        // only the lowest bit of the color samples changed
+        for (i, (a, b)) in original.data().iter().zip(image.data()).enumerate() {
+            assert!(a ^ b <= 1);
+            if i % 4 == 3 {
+                assert_eq!(a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_keyed_order() {
+        let mut image = image(30, 20, 8, ColorType::Truecolor);
+        let original = image.clone();
+        let options = LsbOptions {
+            key: Some(key_from_passphrase("passphrase")),
+            ..LsbOptions::default()
+        };
+
+        embed(&mut image, *b"RuSt", b"Secret message", &options).unwrap();
+        assert_eq!(extract(&image, *b"RuSt", &options).unwrap(), Some(b"Secret message".to_vec()));
+
+        // the bits are spread over the image
+        let changed: Vec<usize> = (0..image.data().len()).filter(|i| image.data()[*i] != original.data()[*i]).collect();
+        assert!(changed.iter().any(|i| *i > 22 * 8 * 2));
+
+        let wrong_key = LsbOptions {
+            key: Some(key_from_passphrase("wrong")),
+            ..LsbOptions::default()
+        };
+        assert!(matches!(extract(&image, *b"RuSt", &wrong_key), Err(PngSecretError::WrongLsbKey)));
+        assert_eq!(extract(&image, *b"RuSt", &LsbOptions::default()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_shuffle() {
+        let mut shuffle = Shuffle::new([7; 32], 1000);
+        let mut order: Vec<usize> = (0..1000).map(|i| shuffle.get(i)).collect();
+        assert_ne!(order, (0..1000).collect::<Vec<usize>>());
+        order.sort();
+        assert_eq!(order, (0..1000).collect::<Vec<usize>>());
+
+        // the same key gives the same order
+        let mut other = Shuffle::new([7; 32], 1000);
+        assert_eq!(other.get(10), Shuffle::new([7; 32], 1000).get(10));
+        assert_eq!(other.get(0), shuffle.get(0));
+    }
+
+    #[test]
+    fn test_wrong_tag() {
+        let mut image = image(20, 10, 8, ColorType::Truecolor);
+        embed(&mut image, *b"RuSt", b"Secret message", &LsbOptions::default()).unwrap();
+        assert_eq!(extract(&image, *b"TeSt", &LsbOptions::default()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_options() {
+        let options = LsbOptions {
+            bits_per_channel: 3,
+            channels: Channels::from_str("ga").unwrap(),
+            key: Some([1; 32]),
+        };
+        for (bit_depth, color_type) in [(16, ColorType::GrayscaleAlpha), (4, ColorType::Grayscale)] {
+            let mut image = image(15, 7, bit_depth, color_type);
+            embed(&mut image, *b"RuSt", b"Secret", &options).unwrap();
+            assert_eq!(extract(&image, *b"RuSt", &options).unwrap(), Some(b"Secret".to_vec()));
+            assert_eq!(extract(&image, *b"RuSt", &LsbOptions::default()).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn test_capacity() {
+        let image = image(10, 10, 8, ColorType::Truecolor);
+        assert_eq!(capacity(&image, &LsbOptions::default()).unwrap(), 300 / 8 - 8);
+
+        let options = LsbOptions {
+            bits_per_channel: 2,
+            channels: Channels::from_str("r").unwrap(),
+            key: None,
+        };
+        assert_eq!(capacity(&image, &options).unwrap(), 200 / 8 - 8);
+    }
+
+    #[test]
+    fn test_message_too_long() {
+        let mut image = image(10, 10, 8, ColorType::Truecolor);
+        let result = embed(&mut image, *b"RuSt", &[0; 30], &LsbOptions::default());
+        assert!(matches!(result, Err(PngSecretError::MessageTooLong { length: 30, capacity: 29 })));
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let mut image = image(10, 10, 8, ColorType::Truecolor);
+        let options = |bits_per_channel, channels| LsbOptions {
+            bits_per_channel,
+            channels: Channels::from_str(channels).unwrap(),
+            key: None,
+        };
+
+        assert!(embed(&mut image, *b"RuSt", b"", &options(0, "rgb")).is_err());
+        assert!(embed(&mut image, *b"RuSt", b"", &options(9, "rgb")).is_err());
+        assert!(embed(&mut image, *b"RuSt", b"", &options(1, "a")).is_err());
+
+        let mut indexed = self::image(10, 10, 8, ColorType::Indexed);
+        assert!(embed(&mut indexed, *b"RuSt", b"", &LsbOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_channels() {
+        let channels = Channels::from_str("bar").unwrap();
+        assert!(channels.red && !channels.green && channels.blue && channels.alpha);
+        assert_eq!(channels.to_string(), "rba");
+
+        assert!(Channels::from_str("").is_err());
+        assert!(Channels::from_str("rgbx").is_err());
+    }
+}
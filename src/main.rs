@@ -1,26 +1,138 @@
-#![allow(unused_variables, dead_code, unused_imports)]
-
 mod args;
-mod chunk;
-mod chunk_type;
 mod commands;
-mod png;
 
-use std::{
-    fs::{self, File, OpenOptions},
-    io::{Read, Write},
-    str::FromStr,
-};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
 
 use clap::Parser;
-use commands::Commands;
+use commands::{Commands, KeyArgs, LsbArgs, Method, RecipientsCommands};
+
+use png_secret::crypto::{self, Key, KeySource};
+use png_secret::recipient::Identity;
+use png_secret::secret::{self, OnExisting, Position, SecretInfo};
+use png_secret::envelope::ContentType;
+use png_secret::split::Reassembled;
+use png_secret::{inspect, lsb, zlib, Attachment, ChunkType, Envelope, LsbOptions, Png, PngSecretError, Severity};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+fn lsb_options(args: &LsbArgs) -> LsbOptions {
+    LsbOptions {
+        bits_per_channel: args.bits_per_channel,
+        channels: args.channels,
+        key: args.lsb_key.as_deref().map(lsb::key_from_passphrase),
+    }
+}
+
+// the key given on the command line, if any
+fn key(args: &KeyArgs) -> Result<Option<Key>> {
+    match (&args.passphrase, &args.key_file) {
+        (Some(passphrase), _) => Ok(Some(Key::Passphrase(passphrase.clone()))),
+        (None, Some(key_file)) => Ok(Some(Key::KeyFile(fs::read(key_file)?))),
+        (None, None) => Ok(None),
+    }
+}
+
+// the envelope of the secret, from its chunks or the pixels
+fn secret_envelope(png: &Png, chunk_type: &str, as_text: Option<&str>, lsb: &LsbArgs) -> Result<Option<Reassembled>> {
+    let whole = |envelope: Envelope| Reassembled { envelope, parts: 1, reordered: false };
+    let envelope = match (lsb.method, as_text) {
+        (Method::Lsb, _) => secret::decode_lsb_envelope(png, chunk_type, &lsb_options(lsb))?.map(whole),
+        (Method::Chunk, Some(keyword)) => secret::decode_text(png, chunk_type, keyword)?.map(|text| whole(text.into())),
+        (Method::Chunk, None) => secret::decode_reassembled(png, chunk_type)?,
+    };
+    Ok(envelope)
+}
+
+// decrypt and decompress the envelope if needed, the passphrase is asked
+// when it wasn't given
+fn reveal(envelope: Envelope, args: &KeyArgs, identity: Option<&Path>) -> Result<Envelope> {
+    let source = envelope.key_source();
+    let key = match (key(args)?, source) {
+        (None, Some(KeySource::Passphrase)) => Some(Key::Passphrase(rpassword::prompt_password("Passphrase : ")?)),
+        (key, _) => key,
+    };
+    let identity = match identity {
+        Some(identity) => Some(Identity::from_file_content(&fs::read_to_string(identity)?)?),
+        None => None,
+    };
+
+    match envelope.reveal(key.as_ref(), identity.as_ref(), zlib::DEFAULT_LIMIT) {
+        Err(PngSecretError::KeyNeeded) if source == Some(KeySource::Recipients) => {
+            Err("The message is encrypted for recipients, use --identity".into())
+        }
+        Err(PngSecretError::KeyNeeded) if source == Some(KeySource::KeyFile) => {
+            Err("The message is encrypted with a key file, use --key-file".into())
+        }
+        revealed => Ok(revealed?),
+    }
+}
 
-use crate::{chunk::Chunk, chunk_type::ChunkType, png::Png};
+// print a secret, or restore it in `output` if it is a file
+fn show(
+    reassembled: Reassembled,
+    file_name: &str,
+    args: &KeyArgs,
+    identity: Option<&Path>,
+    output: Option<&Path>,
+) -> Result<()> {
+    if reassembled.parts > 1 {
+        let order = if reassembled.reordered { ", stored out of order" } else { "" };
+        println!("The secret is split in {} chunks{}.", reassembled.parts, order);
+    }
 
-pub type Error = Box<dyn std::error::Error>;
-pub type Result<T> = std::result::Result<T, Error>;
+    let envelope = reveal(reassembled.envelope, args, identity)?;
+    if envelope.content_type == ContentType::File {
+        let attachment = envelope.attachment()?;
+        println!(
+            "The file in '{}' is '{}' ({}, {} bytes).",
+            file_name,
+            attachment.name(),
+            attachment.mime_type(),
+            attachment.size()
+        );
+        match output {
+            Some(output) => println!("It has been restored to '{}'.", attachment.save_in(output)?.display()),
+            None => println!("Use --output to restore it."),
+        }
+    } else {
+        println!("The message in '{}' is \"{}\".", file_name, envelope.text()?);
+    }
+    Ok(())
+}
+
+// the properties given by the case of the chunk type letters
+fn chunk_flags(chunk_type: &ChunkType) -> String {
+    let flags = [
+        if chunk_type.is_critical() { "critical" } else { "ancillary" },
+        if chunk_type.is_public() { "public" } else { "private" },
+        if chunk_type.is_reserved_bit_valid() { "reserved bit clear" } else { "reserved bit set" },
+        if chunk_type.is_safe_to_copy() { "safe to copy" } else { "unsafe to copy" },
+    ];
+    flags.join(", ")
+}
 
-fn main() -> Result<()> {
+// create `path` readable by its owner only
+fn create_private(path: &Path) -> Result<File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    Ok(options.open(path)?)
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("error: {e}");
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<()> {
     let cli = args::Cli::parse();
 
     match &cli.command {
@@ -30,57 +142,146 @@ fn main() -> Result<()> {
             chunk_type,
             message,
             output_file,
+            file,
+            before_idat,
+            as_text,
+            compress,
+            split,
+            replace,
+            append,
+            fail_if_exists,
+            lsb,
+            key: key_args,
+            recipients,
         }) => {
-            let mut file = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .open(file_path.as_path())?;
-
-            let mut bytes = Vec::new();
-            file.read_to_end(&mut bytes)?;
-
-            // create png instance and add new chunk with the message
-            let mut png = Png::try_from(bytes.as_ref())?;
-            png.append_chunk(Chunk::new(
-                ChunkType::from_str(&chunk_type)?,
-                message.as_bytes().into(),
-            ));
-
-            // write png with the message
-            if let Some(output_file) = output_file {
-                let mut outfile = File::create_new(output_file)?;
-                outfile.write(&png.as_bytes())?;
+            if lsb.method == Method::Lsb && as_text.is_some() {
+                return Err("--as-text can't be used with --method lsb".into());
+            }
+            if split.is_some() && (lsb.method == Method::Lsb || as_text.is_some()) {
+                return Err("--split only works with chunks, not with --method lsb or --as-text".into());
+            }
 
-                println!(
-                    "The message has been added to '{}'.",
-                    output_file.file_name().unwrap().to_string_lossy()
-                );
-            } else {
-                fs::write(file_path, png.as_bytes())?;
-                // file.write(&png.as_bytes())?; doesn't work ?
+            let on_existing = match (replace, append, fail_if_exists) {
+                (true, _, _) => Some(OnExisting::Replace),
+                (_, true, _) => Some(OnExisting::Append),
+                (_, _, true) => Some(OnExisting::Fail),
+                _ => None,
+            };
+            if on_existing.is_some() && as_text.is_some() {
+                return Err("--replace, --append and --fail-if-exists can't be used with --as-text".into());
+            }
+            if on_existing == Some(OnExisting::Append) && lsb.method == Method::Lsb {
+                return Err("--append can't be used with --method lsb, the pixels hold one secret".into());
+            }
 
-                println!(
-                    "The message has been added to '{}'.",
-                    file_path.file_name().unwrap().to_string_lossy()
-                );
+            let key = key(key_args)?;
+            if (key.is_some() || !recipients.is_empty() || file.is_some() || compress.is_some()) && as_text.is_some() {
+                return Err("--as-text can't be used with encryption, compression or --file".into());
             }
+
+            let envelope = match (file, message) {
+                (Some(file), _) => Envelope::from(&Attachment::from_path(file)?),
+                (None, Some(message)) => Envelope::from(message.as_str()),
+                (None, None) => return Err("A message or --file is needed".into()),
+            };
+            let envelope = match compress {
+                Some(compression) => envelope.compress(*compression)?,
+                None => envelope,
+            };
+            let envelope = match &key {
+                Some(key) => envelope.encrypt(key)?,
+                None if !recipients.is_empty() => envelope.encrypt_to(recipients)?,
+                None => envelope,
+            };
+
+            let position = if *before_idat {
+                Position::BeforeIdat
+            } else {
+                Position::BeforeIend
+            };
+
+            // copy the png with the new message chunk
+            secret::rewrite_file(file_path, output_file.as_deref(), |input, mut output| match (lsb.method, as_text) {
+                (Method::Lsb, _) => {
+                    // the pixels are rewritten, the whole png is needed
+                    let mut png = Png::from_reader(input)?;
+                    let options = lsb_options(lsb);
+                    if on_existing == Some(OnExisting::Fail)
+                        && secret::decode_lsb_envelope(&png, chunk_type, &options).is_ok_and(|envelope| envelope.is_some())
+                    {
+                        return Err(PngSecretError::ChunkExists(chunk_type.clone()));
+                    }
+                    secret::encode_lsb(&mut png, chunk_type, envelope, &options)?;
+                    output.write_all(&png.as_bytes())?;
+                    output.flush()?;
+                    Ok(output)
+                }
+                (Method::Chunk, Some(keyword)) => {
+                    let chunk = secret::text_chunk(chunk_type, keyword, &envelope.text()?)?;
+                    secret::encode_chunk_stream(input, output, chunk, position)
+                }
+                (Method::Chunk, None) => {
+                    let chunks = match split {
+                        Some(part_size) => secret::split_chunks(chunk_type, envelope, *part_size)?,
+                        None => vec![secret::secret_chunk(chunk_type, envelope)?],
+                    };
+                    secret::encode_chunks_stream(input, output, chunks, position, on_existing.unwrap_or_default())
+                }
+            })?;
+
+            let written_file = output_file.as_ref().unwrap_or(file_path);
+            println!(
+                "The message has been added to '{}'.",
+                written_file.file_name().unwrap().to_string_lossy()
+            );
         }
 
         Some(Commands::Decode {
             file_path,
             chunk_type,
+            as_text,
+            all,
+            index,
+            lsb,
+            key: key_args,
+            identity,
+            output,
         }) => {
-            // println!("Decoding...");
             let png = Png::from_path(file_path)?;
+            let file_name = file_path.file_name().unwrap().to_string_lossy();
+
+            if (*all || index.is_some()) && (lsb.method == Method::Lsb || as_text.is_some()) {
+                return Err("--all and --index only work with chunks, not with --method lsb or --as-text".into());
+            }
 
-            match png.chunk_by_type(chunk_type) {
-                Some(chunk) => {
-                    println!(
-                        "The message in '{}' is \"{}\".",
-                        file_path.file_name().unwrap().to_string_lossy(),
-                        chunk.data_as_string()?
-                    );
+            if *all {
+                let secrets = secret::decode_all(&png, chunk_type)?;
+                if secrets.is_empty() {
+                    println!("Message not found");
                 }
+                // a secret that can't be read doesn't stop the others
+                for (index, reassembled) in secrets.into_iter().enumerate() {
+                    println!("Secret {} :", index);
+                    let shown = reassembled
+                        .map_err(|e| e.into())
+                        .and_then(|reassembled| show(reassembled, &file_name, key_args, identity.as_deref(), output.as_deref()));
+                    if let Err(e) = shown {
+                        println!("{}", e);
+                    }
+                }
+                return Ok(());
+            }
+
+            let reassembled = match index {
+                Some(index) => {
+                    let mut secrets = secret::decode_all(&png, chunk_type)?.into_iter();
+                    Some(secrets.nth(*index).ok_or(PngSecretError::InvalidIndex(*index))??)
+                }
+                None => secret_envelope(&png, chunk_type, as_text.as_deref(), lsb)?,
+            };
+
+            match reassembled {
+                Some(reassembled) => show(reassembled, &file_name, key_args, identity.as_deref(), output.as_deref())?,
                 None => println!("Message not found"),
             };
         }
@@ -89,42 +290,139 @@ fn main() -> Result<()> {
             file_path,
             chunk_type,
         }) => {
-            let mut png = Png::from_path(&file_path)?;
+            let removed = secret::rewrite_file(file_path, None, |input, output| {
+                secret::remove_stream(input, output, chunk_type)
+            });
 
-            match png.remove_chunk(chunk_type) {
-                Ok(chunk) => {
-                    fs::write(file_path, png.as_bytes())?;
-                    println!("\"{}\" message has been removed.", chunk.data_as_string()?);
+            match removed {
+                Ok((chunk, _)) => match Envelope::read(chunk.data()).and_then(|envelope| envelope.text()) {
+                    Ok(message) => println!("\"{}\" message has been removed.", message),
+                    Err(_) => println!("The '{}' secret has been removed.", chunk.chunk_type()),
                 },
                 Err(e) => println!("{}", e)
             }
         }
 
         Some(Commands::Print { file_path }) => {
-            let png = Png::from_path(&file_path)?;
-
-            // filter chunks to get only secret chunk
-            let secret_chunk = png.chunks().into_iter().filter(|chunk| {
-                if let Ok(string) = chunk.data_as_string() {
-                    let chunk_type = chunk.chunk_type().to_string();
-                    if !chunk_type.eq("sBIT") && !chunk_type.eq("IEND") {
-                        true
-                    } else {
-                        false
+            let png = Png::from_path(file_path)?;
+
+            if let Ok(ihdr) = png.ihdr() {
+                println!("Image : {}", ihdr);
+            }
+
+            let secret_chunks = secret::list(&png);
+
+            if !secret_chunks.is_empty() {
+                for chunk in secret_chunks {
+                    let chunk_type = chunk.chunk_type();
+                    match secret::describe(chunk) {
+                        Ok(SecretInfo::Part(part)) => println!(
+                            "Key '{}' has part {} of {} of secret {:08x}",
+                            chunk_type,
+                            part.index + 1,
+                            part.total,
+                            part.payload_id
+                        ),
+                        Ok(SecretInfo::Encrypted) => println!("Key '{}' has an encrypted secret", chunk_type),
+                        Ok(SecretInfo::File(file)) => println!(
+                            "Key '{}' has file '{}' ({}, {} bytes)",
+                            chunk_type,
+                            file.name(),
+                            file.mime_type(),
+                            file.size()
+                        ),
+                        Ok(SecretInfo::Text(text)) => println!("Key '{}' has secret : \"{}\"", chunk_type, text),
+                        Ok(SecretInfo::Binary(length)) => println!("Key '{}' has a secret of {} bytes", chunk_type, length),
+                        Err(e) => println!("Key '{}' : {}", chunk_type, e),
                     }
+                }
+            } else {
+                println!("No secret found.");
+            }
+
+            for text in secret::list_text(&png) {
+                match text.text() {
+                    Ok(content) => println!("Keyword '{}' has text : \"{}\"", text.keyword(), content),
+                    Err(e) => println!("Keyword '{}' : {}", text.keyword(), e),
+                }
+            }
+
+        }
+
+        Some(Commands::Inspect { file_path }) => {
+            let bytes = fs::read(file_path)?;
+            let inspection = inspect::inspect(&bytes)?;
+
+            println!("File : {} ({} bytes)", file_path.display(), bytes.len());
+            for chunk in &inspection.chunks {
+                println!(
+                    "chunk {} at offset {:#07x}, length {}",
+                    String::from_utf8_lossy(&chunk.chunk_type),
+                    chunk.offset,
+                    chunk.length
+                );
+                match chunk.chunk_type() {
+                    Ok(chunk_type) => println!("    {}", chunk_flags(&chunk_type)),
+                    Err(e) => println!("    {}", e),
+                }
+                if chunk.is_crc_valid() {
+                    println!("    CRC {:08x}", chunk.stored_crc);
                 } else {
-                    false
+                    println!("    CRC {:08x}, computed {:08x}, mismatch", chunk.stored_crc, chunk.computed_crc);
                 }
-            });
+                if let Some(summary) = &chunk.summary {
+                    println!("    {}", summary);
+                }
+            }
+
+            if let Some(e) = &inspection.error {
+                println!("{}", e);
+            }
+            let violations = inspection.validate();
+            for violation in &violations {
+                let severity = match violation.severity() {
+                    Severity::Warning => "Warning",
+                    Severity::Error => "Error",
+                };
+                println!("{} : {}", severity, violation);
+            }
 
-            if secret_chunk.clone().count() > 0 {
-                for chunk in secret_chunk.clone() {
-                    println!("Key '{}' has secret : \"{}\"", chunk.chunk_type(), chunk.data_as_string()?);
+            let errors = inspection.crc_errors()
+                + usize::from(inspection.error.is_some())
+                + violations.iter().filter(|violation| violation.severity() == Severity::Error).count();
+            match errors {
+                0 => println!("{} chunks, no errors.", inspection.chunks.len()),
+                errors => println!("{} chunks, {} errors.", inspection.chunks.len(), errors),
+            }
+        }
+
+        Some(Commands::Keygen { output }) => {
+            let identity = Identity::generate();
+
+            match output {
+                Some(output) => {
+                    create_private(output)?.write_all(identity.to_file_content().as_bytes())?;
+                    println!("Public key : {}", identity.recipient());
+                    println!("Fingerprint : {}", identity.recipient().fingerprint());
                 }
-            } else {
-                println!("No secret found.");
+                None => print!("{}", identity.to_file_content()),
             }
+        }
+
+        Some(Commands::Recipients {
+            command: RecipientsCommands::List { file_path, chunk_type, lsb },
+        }) => {
+            let png = Png::from_path(file_path)?;
 
+            match secret_envelope(&png, chunk_type, None, lsb)?.map(|reassembled| reassembled.envelope) {
+                Some(envelope) if crypto::key_source(&envelope.payload) == Some(KeySource::Recipients) => {
+                    for fingerprint in crypto::recipients(&envelope.payload)? {
+                        println!("{}", fingerprint);
+                    }
+                }
+                Some(_) => println!("The message isn't encrypted for recipients"),
+                None => println!("Message not found"),
+            }
         }
 
         None => {}
@@ -1,14 +1,20 @@
 #![allow(unused_variables)]
 
 use std::fmt::Display;
-use std::fs::{self, File};
-use std::io::Read;
-use std::path::{self, Path};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
 use std::str::FromStr;
 
 use crate::chunk_type::ChunkType;
-use crate::{chunk, Error, Result};
+use crate::{PngSecretError, Result};
 use crate::chunk::Chunk;
+use crate::filter::FilterStrategy;
+use crate::ihdr::Ihdr;
+use crate::image::{self, Image};
+use crate::png_ref::PngRef;
+use crate::reader::ChunkReader;
+use crate::validate::{self, Severity, Violation};
 
 #[derive(Debug, Clone)]
 pub struct Png {
@@ -16,7 +22,10 @@ pub struct Png {
 }
 
 impl Png {
-    const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+    pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+    // size of the `IDAT` chunks written by `set_image`
+    const IDAT_SIZE: usize = 64 * 1024;
 
     pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
         Png {
@@ -25,30 +34,67 @@ impl Png {
     }
 
     pub fn from_path(path: &Path) -> Result<Png> {
-        let mut file = File::open(path)?;
+        let file = File::open(path)?;
+
+        Png::from_reader(BufReader::new(file))
+    }
+
+    /// Parse `bytes` like `try_from`, but reject a png breaking the chunk
+    /// ordering rules of the spec. Warnings are accepted.
+    pub fn try_from_strict(bytes: &[u8]) -> Result<Png> {
+        let png = Png::try_from(bytes)?;
+
+        let errors: Vec<Violation> = png.validate()
+            .into_iter()
+            .filter(|violation| violation.severity() == Severity::Error)
+            .collect();
 
-        let mut bytes = Vec::new();
-        file.read_to_end(&mut bytes)?;
+        if !errors.is_empty() {
+            return Err(PngSecretError::InvalidStructure(errors));
+        }
 
-        Ok(Png::try_from(bytes.as_ref())?)
+        Ok(png)
+    }
+
+    pub fn from_reader<R: Read>(reader: R) -> Result<Png> {
+        let chunks = ChunkReader::new(reader)?.collect::<Result<Vec<Chunk>>>()?;
+
+        Ok(Png::from_chunks(chunks))
     }
 
     pub fn append_chunk(&mut self, chunk: Chunk) {
         self.chunks.push(chunk);
     }
 
-    pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
-        match self.chunk_by_type(chunk_type) {
-            Some(chunk) => {
-                let chunk_index = self.chunks.iter().position(|c| {
-                    c.eq(chunk)
-                }).unwrap();
+    /// Insert `chunk` at `index`, shifting every following chunk.
+    pub fn insert_chunk(&mut self, index: usize, chunk: Chunk) -> Result<()> {
+        if index > self.chunks.len() {
+            return Err(PngSecretError::InvalidIndex(index));
+        }
+        self.chunks.insert(index, chunk);
+        Ok(())
+    }
 
-                let chunk_deleted = self.chunks.remove(chunk_index);
+    /// Insert `chunk` right before the first chunk of type `chunk_type`.
+    pub fn insert_before(&mut self, chunk_type: &str, chunk: Chunk) -> Result<()> {
+        match self.position_of(chunk_type) {
+            Some(index) => self.insert_chunk(index, chunk),
+            None => Err(PngSecretError::ChunkNotFound(chunk_type.to_string()))
+        }
+    }
 
-                Ok(chunk_deleted)
-            },
-            None => Err("Chunk doesn't exits".into())
+    /// Insert `chunk` right after the first chunk of type `chunk_type`.
+    pub fn insert_after(&mut self, chunk_type: &str, chunk: Chunk) -> Result<()> {
+        match self.position_of(chunk_type) {
+            Some(index) => self.insert_chunk(index + 1, chunk),
+            None => Err(PngSecretError::ChunkNotFound(chunk_type.to_string()))
+        }
+    }
+
+    pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
+        match self.position_of(chunk_type) {
+            Some(chunk_index) => Ok(self.chunks.remove(chunk_index)),
+            None => Err(PngSecretError::ChunkNotFound(chunk_type.to_string()))
         }
     }
 
@@ -61,80 +107,101 @@ impl Png {
     }
 
     pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
-        match ChunkType::from_str(chunk_type) {
-            Err(_) => None,
-            Ok(chunk_type) => {
-                let find_chunk_type = chunk_type;
-
-                let chunk_index = self.chunks.iter().position(|chunk| {
-                    chunk.chunk_type().eq(&find_chunk_type)
-                });
-
-                match chunk_index {
-                    Some(c) => Some(&self.chunks[c]),
-                    None => None
-                }
-            }
-        }
+        self.position_of(chunk_type).map(|index| &self.chunks[index])
     }
 
-    pub fn as_bytes(&self) -> Vec<u8> {
-        let mut res = Vec::<u8>::new();
-
-        res.extend(Png::STANDARD_HEADER.iter());
+    /// Every chunk of type `chunk_type`, in order. Empty if `chunk_type`
+    /// isn't a valid chunk type.
+    pub fn chunks_by_type(&self, chunk_type: &str) -> impl Iterator<Item = &Chunk> {
+        let find_chunk_type = ChunkType::from_str(chunk_type).ok();
+        self.chunks.iter().filter(move |chunk| Some(chunk.chunk_type()) == find_chunk_type.as_ref())
+    }
 
-        for chunk in self.chunks() {
-            res.extend(chunk.as_bytes());
+    /// The image header, read from the `IHDR` chunk.
+    pub fn ihdr(&self) -> Result<Ihdr> {
+        match self.chunk_by_type("IHDR") {
+            Some(chunk) => Ihdr::try_from(chunk),
+            None => Err(PngSecretError::ChunkNotFound("IHDR".to_string()))
         }
-
-        res
     }
-}
 
-impl TryFrom<&[u8]> for Png {
-    type Error = Error;
+    /// Decode the pixels stored in the `IDAT` chunks.
+    pub fn image(&self) -> Result<Image> {
+        let ihdr = self.ihdr()?;
 
-    fn try_from(value: &[u8]) -> Result<Self> {
-        let mut value = value;
+        let idat: Vec<u8> = self.chunks.iter()
+            .filter(|chunk| chunk.chunk_type().to_string() == "IDAT")
+            .flat_map(|chunk| chunk.data().iter().copied())
+            .collect();
+        if idat.is_empty() {
+            return Err(PngSecretError::ChunkNotFound("IDAT".to_string()));
+        }
 
-        // get header
-        let mut header: [u8; 8] = [0; 8];
-        value.read_exact(&mut header)?;
+        image::decode(ihdr, idat.as_slice())
+    }
+
+    /// Replace the pixels with `image`, writing a new `IHDR` and new `IDAT`
+    /// chunks where the old ones were.
+    pub fn set_image(&mut self, image: &Image) {
+        self.set_image_with(image, FilterStrategy::default());
+    }
 
-        // check header
-        if header != Png::STANDARD_HEADER {
-            return Err("Invalid header".into());
+    /// Same as [`Png::set_image`], choosing the row filters with `strategy`.
+    pub fn set_image_with(&mut self, image: &Image, strategy: FilterStrategy) {
+        let idat = image::encode(image, strategy);
+        let idat_chunks = idat.chunks(Png::IDAT_SIZE).map(|data| {
+            Chunk::new(ChunkType::from_str("IDAT").expect("valid chunk type"), data.to_vec())
+        });
+
+        let ihdr = Chunk::from(image.ihdr());
+        match self.position_of("IHDR") {
+            Some(index) => self.chunks[index] = ihdr,
+            None => self.chunks.insert(0, ihdr),
         }
 
-        // retrieve all chunks
-        // length [4 bytes] + chunk type [4 bytes] + data [length bytes] + crc [4 bytes]
-        let mut chunks = Vec::new();
-        let mut length_bytes: [u8; 4] = [0; 4];
+        let index = self.position_of("IDAT")
+            .or_else(|| self.position_of("IEND"))
+            .unwrap_or(self.chunks.len());
+        self.chunks.retain(|chunk| chunk.chunk_type().to_string() != "IDAT");
+        self.chunks.splice(index..index, idat_chunks);
+    }
 
-        while !value.is_empty() {
-            let mut chunk_data = Vec::<u8>::new();
+    // index of the first chunk of type `chunk_type`
+    fn position_of(&self, chunk_type: &str) -> Option<usize> {
+        let find_chunk_type = ChunkType::from_str(chunk_type).ok()?;
 
-            // get current chunk data length
-            value.read_exact(&mut length_bytes)?;
-            let length = u32::from_be_bytes(length_bytes);
+        self.chunks.iter().position(|chunk| {
+            chunk.chunk_type().eq(&find_chunk_type)
+        })
+    }
 
-            let chunk_length: usize = 8 + length as usize;
+    /// Every chunk ordering rule of the spec broken by this png.
+    pub fn validate(&self) -> Vec<Violation> {
+        let chunk_types: Vec<String> = self.chunks.iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
 
-            let mut chunk_suite = vec![0; chunk_length];
-            value.read_exact(&mut chunk_suite)?;
+        validate::validate(&chunk_types)
+    }
 
-            // add all chunk data
-            chunk_data.extend(length_bytes.iter());
-            chunk_data.extend(chunk_suite.iter());
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut res = Vec::<u8>::new();
 
-            let bytes: &[u8] = &chunk_data;
+        res.extend(Png::STANDARD_HEADER.iter());
 
-            chunks.push(
-                Chunk::try_from(bytes)?
-            )
+        for chunk in self.chunks() {
+            res.extend(chunk.as_bytes());
         }
 
-        Ok(Png::from_chunks(chunks))
+        res
+    }
+}
+
+impl TryFrom<&[u8]> for Png {
+    type Error = PngSecretError;
+
+    fn try_from(value: &[u8]) -> Result<Self> {
+        PngRef::try_from(value).map(|png| png.to_png())
     }
 }
 
@@ -150,7 +217,8 @@ mod tests {
     use super::*;
     use crate::chunk_type::ChunkType;
     use crate::chunk::Chunk;
-    use std::str::FromStr;
+    use crate::filter::FilterType;
+    use crate::ihdr::InterlaceMethod;
     use std::convert::TryFrom;
 
     fn testing_chunks() -> Vec<Chunk> {
@@ -216,7 +284,7 @@ mod tests {
 
         let png = Png::try_from(bytes.as_ref());
 
-        assert!(png.is_err());
+        assert!(matches!(png, Err(PngSecretError::InvalidSignature)));
     }
 
     #[test]
@@ -242,6 +310,38 @@ mod tests {
     }
 
 
+    #[test]
+    fn test_truncated_png() {
+        // a png cut right between two chunks is still readable
+        let mut chunk_ends = vec![Png::STANDARD_HEADER.len()];
+        for chunk in Png::try_from(&PNG_FILE[..]).unwrap().chunks() {
+            chunk_ends.push(chunk_ends.last().unwrap() + chunk.as_bytes().len());
+        }
+
+        for length in 0..PNG_FILE.len() {
+            let png = Png::try_from(&PNG_FILE[..length]);
+            assert_eq!(png.is_ok(), chunk_ends.contains(&length));
+        }
+    }
+
+    #[test]
+    fn test_chunk_length_too_big() {
+        #[rustfmt::skip]
+        let bytes: Vec<u8> = Png::STANDARD_HEADER
+            .iter()
+            .chain([
+                255, 255, 255, 255, // length
+                82, 117, 83, 116,   // chunk type
+                1, 2, 3, 4,         // CRC
+            ].iter())
+            .copied()
+            .collect();
+
+        let png = Png::try_from(bytes.as_ref());
+
+        assert!(matches!(png, Err(PngSecretError::TruncatedChunk { offset: 8 })));
+    }
+
     #[test]
     fn test_list_chunks() {
         let png = testing_png();
@@ -258,6 +358,17 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_chunks_by_type() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("FrSt", "I am the last chunk").unwrap());
+
+        let chunks: Vec<String> = png.chunks_by_type("FrSt").map(|chunk| chunk.data_as_string().unwrap()).collect();
+        assert_eq!(chunks, vec!["I am the first chunk", "I am the last chunk"]);
+        assert_eq!(png.chunks_by_type("TeSt").count(), 0);
+        assert_eq!(png.chunks_by_type("T3St").count(), 0);
+    }
+
     #[test]
     fn test_append_chunk() {
         let mut png = testing_png();
@@ -276,12 +387,187 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_insert_chunk() {
+        let mut png = testing_png();
+        png.insert_chunk(1, chunk_from_strings("TeSt", "Message").unwrap()).unwrap();
+        assert_eq!(&png.chunks()[1].chunk_type().to_string(), "TeSt");
+        assert_eq!(png.chunks().len(), 4);
+
+        png.insert_chunk(4, chunk_from_strings("EnDs", "Message").unwrap()).unwrap();
+        assert_eq!(&png.chunks()[4].chunk_type().to_string(), "EnDs");
+    }
+
+    #[test]
+    fn test_insert_chunk_out_of_bounds() {
+        let mut png = testing_png();
+        let result = png.insert_chunk(4, chunk_from_strings("TeSt", "Message").unwrap());
+        assert!(matches!(result, Err(PngSecretError::InvalidIndex(4))));
+        assert_eq!(png.chunks().len(), 3);
+    }
+
+    #[test]
+    fn test_insert_before() {
+        let mut png = testing_png();
+        png.insert_before("LASt", chunk_from_strings("TeSt", "Message").unwrap()).unwrap();
+        let chunk_types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(chunk_types, vec!["FrSt", "miDl", "TeSt", "LASt"]);
+    }
+
+    #[test]
+    fn test_insert_after() {
+        let mut png = testing_png();
+        png.insert_after("FrSt", chunk_from_strings("TeSt", "Message").unwrap()).unwrap();
+        let chunk_types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(chunk_types, vec!["FrSt", "TeSt", "miDl", "LASt"]);
+    }
+
+    #[test]
+    fn test_insert_before_missing_chunk() {
+        let mut png = testing_png();
+        let result = png.insert_before("IEND", chunk_from_strings("TeSt", "Message").unwrap());
+        assert!(matches!(result, Err(PngSecretError::ChunkNotFound(_))));
+        let result = png.insert_after("IEND", chunk_from_strings("TeSt", "Message").unwrap());
+        assert!(matches!(result, Err(PngSecretError::ChunkNotFound(_))));
+    }
+
+    #[test]
+    fn test_remove_missing_chunk() {
+        let mut png = testing_png();
+        let chunk = png.remove_chunk("TeSt");
+        assert!(matches!(chunk, Err(PngSecretError::ChunkNotFound(_))));
+    }
+
+    #[test]
+    fn test_crc_mismatch_offset() {
+        let mut bytes = PNG_FILE.to_vec();
+        // corrupt the last byte of the IHDR crc, the chunk starts right after the signature
+        bytes[32] ^= 0xff;
+
+        let png = Png::try_from(bytes.as_ref());
+
+        match png {
+            Err(PngSecretError::CrcMismatch { chunk_type, offset, .. }) => {
+                assert_eq!(chunk_type.to_string(), "IHDR");
+                assert_eq!(offset, 8);
+            }
+            _ => panic!("expected a CRC mismatch"),
+        }
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
         assert!(png.is_ok());
     }
 
+    // the dice image without its unknown critical `RuSt` chunk
+    fn valid_png() -> Png {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.remove_chunk("RuSt").unwrap();
+        png
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(valid_png().validate().is_empty());
+
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert_eq!(
+            png.validate(),
+            vec![Violation::UnknownCritical { chunk_type: "RuSt".to_string(), index: 5 }]
+        );
+
+        let violations = testing_png().validate();
+        assert!(violations.contains(&Violation::MissingIhdr));
+    }
+
+    #[test]
+    fn test_try_from_strict() {
+        assert!(Png::try_from_strict(&valid_png().as_bytes()).is_ok());
+
+        // secret after IEND
+        let mut png = valid_png();
+        png.append_chunk(chunk_from_strings("ruSt", "Message").unwrap());
+        let png = Png::try_from_strict(&png.as_bytes());
+        match png {
+            Err(PngSecretError::InvalidStructure(violations)) => {
+                assert_eq!(violations, vec![Violation::IendNotLast { index: 6 }]);
+            }
+            _ => panic!("expected an invalid structure"),
+        }
+    }
+
+    #[test]
+    fn test_try_from_strict_accepts_warnings() {
+        let mut png = valid_png();
+        png.insert_before("IEND", chunk_from_strings("gAMA", "Message").unwrap()).unwrap();
+        assert!(!png.validate().is_empty());
+        assert!(Png::try_from_strict(&png.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn test_ihdr() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let ihdr = png.ihdr().unwrap();
+        assert_eq!(ihdr.width(), 50);
+        assert_eq!(ihdr.height(), 50);
+        assert_eq!(ihdr.bit_depth(), 8);
+
+        assert!(matches!(testing_png().ihdr(), Err(PngSecretError::ChunkNotFound(_))));
+    }
+
+    #[test]
+    fn test_image() {
+        let image = valid_png().image().unwrap();
+        assert_eq!(image.width(), 50);
+        assert_eq!(image.data().len(), image.stride() * 50);
+
+        assert!(matches!(testing_png().image(), Err(PngSecretError::ChunkNotFound(_))));
+    }
+
+    #[test]
+    fn test_set_image() {
+        let mut png = valid_png();
+        let mut image = png.image().unwrap();
+        image.data_mut()[0] ^= 1;
+        image.set_interlace_method(InterlaceMethod::Adam7);
+
+        png.set_image(&image);
+        let chunk_types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(chunk_types, vec!["IHDR", "sRGB", "gAMA", "pHYs", "IDAT", "IEND"]);
+        assert!(png.validate().is_empty());
+
+        let png = Png::try_from(png.as_bytes().as_ref()).unwrap();
+        assert_eq!(png.ihdr().unwrap().interlace_method(), InterlaceMethod::Adam7);
+        assert_eq!(png.image().unwrap(), image);
+    }
+
+    #[test]
+    fn test_set_image_with() {
+        let mut png = valid_png();
+        let image = png.image().unwrap();
+        let original_size = png.as_bytes().len();
+
+        png.set_image_with(&image, FilterStrategy::Fixed(FilterType::None));
+        assert_eq!(png.image().unwrap(), image);
+        let unfiltered_size = png.as_bytes().len();
+
+        png.set_image_with(&image, FilterStrategy::BruteForce);
+        assert_eq!(png.image().unwrap(), image);
+        assert!(png.as_bytes().len() <= unfiltered_size);
+
+        png.set_image(&image);
+        assert_eq!(png.image().unwrap(), image);
+        assert!(png.as_bytes().len() < unfiltered_size.min(original_size));
+    }
+
+    #[test]
+    fn test_png_from_reader() {
+        let png = Png::from_reader(&PNG_FILE[..]).unwrap();
+        assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+    }
+
     #[test]
     fn test_as_bytes() {
         let png = Png::try_from(&PNG_FILE[..]).unwrap();
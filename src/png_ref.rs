@@ -0,0 +1,173 @@
+use std::str::FromStr;
+
+use crate::chunk_ref::ChunkRef;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::validate::{self, Violation};
+use crate::{PngSecretError, Result};
+
+/// A png borrowed from the bytes it was read from.
+///
+/// Every chunk is checked once by `try_from`, then [`PngRef::chunks`] gives
+/// [`ChunkRef`]s slicing into the original buffer without any allocation.
+#[derive(Debug, Clone, Copy)]
+pub struct PngRef<'a> {
+    // every chunk, after the signature
+    bytes: &'a [u8],
+}
+
+impl<'a> PngRef<'a> {
+    pub fn chunks(&self) -> ChunkRefs<'a> {
+        ChunkRefs { bytes: self.bytes }
+    }
+
+    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<ChunkRef<'a>> {
+        let find_chunk_type = ChunkType::from_str(chunk_type).ok()?;
+        self.chunks().find(|chunk| chunk.chunk_type().eq(&find_chunk_type))
+    }
+
+    /// Every chunk of type `chunk_type`, in order.
+    pub fn chunks_by_type(&self, chunk_type: &str) -> impl Iterator<Item = ChunkRef<'a>> {
+        let find_chunk_type = ChunkType::from_str(chunk_type).ok();
+        self.chunks().filter(move |chunk| Some(chunk.chunk_type()) == find_chunk_type.as_ref())
+    }
+
+    /// Every chunk ordering rule of the spec broken by this png.
+    pub fn validate(&self) -> Vec<Violation> {
+        let chunk_types: Vec<String> = self.chunks()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+
+        validate::validate(&chunk_types)
+    }
+
+    pub fn to_png(&self) -> Png {
+        Png::from_chunks(self.chunks().map(|chunk| chunk.to_chunk()).collect())
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for PngRef<'a> {
+    type Error = PngSecretError;
+
+    fn try_from(value: &'a [u8]) -> Result<Self> {
+        // check header
+        if value.get(..8) != Some(&Png::STANDARD_HEADER[..]) {
+            return Err(PngSecretError::InvalidSignature);
+        }
+        let bytes = &value[8..];
+
+        // check all chunks
+        let mut remaining = bytes;
+        let mut offset = Png::STANDARD_HEADER.len();
+        while !remaining.is_empty() {
+            let chunk = ChunkRef::try_from(remaining).map_err(|e| e.at_offset(offset))?;
+            let chunk_length = chunk.as_bytes().len();
+
+            remaining = &remaining[chunk_length..];
+            offset += chunk_length;
+        }
+
+        Ok(PngRef { bytes })
+    }
+}
+
+/// Iterator over the chunks of a [`PngRef`].
+pub struct ChunkRefs<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Iterator for ChunkRefs<'a> {
+    type Item = ChunkRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.bytes.is_empty() {
+            return None;
+        }
+        // chunks were checked when creating the `PngRef`
+        let chunk = ChunkRef::from_checked(self.bytes);
+        self.bytes = &self.bytes[chunk.as_bytes().len()..];
+        Some(chunk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("FrSt").unwrap(), b"I am the first chunk".to_vec()),
+            Chunk::new(ChunkType::from_str("miDl").unwrap(), b"I am another chunk".to_vec()),
+            Chunk::new(ChunkType::from_str("LASt").unwrap(), b"I am the last chunk".to_vec()),
+        ])
+    }
+
+    #[test]
+    fn test_png_ref_chunks() {
+        let bytes = testing_png().as_bytes();
+        let png = PngRef::try_from(bytes.as_ref()).unwrap();
+
+        let chunk_types: Vec<String> = png.chunks().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(chunk_types, vec!["FrSt", "miDl", "LASt"]);
+    }
+
+    #[test]
+    fn test_png_ref_chunk_by_type() {
+        let bytes = testing_png().as_bytes();
+        let png = PngRef::try_from(bytes.as_ref()).unwrap();
+
+        let chunk = png.chunk_by_type("miDl").unwrap();
+        assert_eq!(chunk.data(), b"I am another chunk");
+        assert!(png.chunk_by_type("TeSt").is_none());
+        assert!(png.chunk_by_type("T3St").is_none());
+    }
+
+    #[test]
+    fn test_png_ref_chunks_by_type() {
+        let mut png = testing_png();
+        png.append_chunk(Chunk::new(ChunkType::from_str("miDl").unwrap(), b"Again".to_vec()));
+        let bytes = png.as_bytes();
+        let png = PngRef::try_from(bytes.as_ref()).unwrap();
+
+        let data: Vec<&[u8]> = png.chunks_by_type("miDl").map(|chunk| chunk.data()).collect();
+        assert_eq!(data, vec![b"I am another chunk".as_slice(), b"Again"]);
+        assert_eq!(png.chunks_by_type("T3St").count(), 0);
+    }
+
+    #[test]
+    fn test_to_png() {
+        let bytes = testing_png().as_bytes();
+        let png = PngRef::try_from(bytes.as_ref()).unwrap();
+
+        assert_eq!(png.to_png().as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_png_ref_validate() {
+        let bytes = testing_png().as_bytes();
+        let png = PngRef::try_from(bytes.as_ref()).unwrap();
+
+        assert_eq!(png.validate(), testing_png().validate());
+    }
+
+    #[test]
+    fn test_png_ref_invalid_header() {
+        let mut bytes = testing_png().as_bytes();
+        bytes[0] = 13;
+
+        assert!(matches!(PngRef::try_from(bytes.as_ref()), Err(PngSecretError::InvalidSignature)));
+        assert!(matches!(PngRef::try_from(&bytes[..3]), Err(PngSecretError::InvalidSignature)));
+    }
+
+    #[test]
+    fn test_png_ref_invalid_chunk() {
+        let mut bytes = testing_png().as_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+
+        let png = PngRef::try_from(bytes.as_ref());
+
+        assert!(matches!(png, Err(PngSecretError::CrcMismatch { .. })));
+    }
+}
@@ -0,0 +1,236 @@
+use std::io::{ErrorKind, Read};
+
+use crate::chunk::Chunk;
+use crate::png::Png;
+use crate::{PngSecretError, Result};
+
+/// Read the chunks of a png one at a time from any [`Read`].
+///
+/// The signature is checked by [`ChunkReader::new`], then every call to
+/// `next` reads a single chunk, so the whole file never has to be in memory.
+/// The iteration stops at the end of the input or after the first error.
+///
+/// ```no_run
+/// use std::fs::File;
+/// use std::io::BufReader;
+/// use png_secret::ChunkReader;
+///
+/// let file = File::open("assets/cat.png")?;
+/// for chunk in ChunkReader::new(BufReader::new(file))? {
+///     println!("{}", chunk?.chunk_type());
+/// }
+/// # Ok::<(), png_secret::PngSecretError>(())
+/// ```
+pub struct ChunkReader<R: Read> {
+    reader: R,
+    offset: usize,
+    done: bool,
+}
+
+impl<R: Read> ChunkReader<R> {
+    pub fn new(mut reader: R) -> Result<ChunkReader<R>> {
+        let mut header: [u8; 8] = [0; 8];
+        reader.read_exact(&mut header).map_err(|e| match e.kind() {
+            ErrorKind::UnexpectedEof => PngSecretError::InvalidSignature,
+            _ => PngSecretError::Io(e),
+        })?;
+
+        if header != Png::STANDARD_HEADER {
+            return Err(PngSecretError::InvalidSignature);
+        }
+
+        Ok(ChunkReader {
+            reader,
+            offset: header.len(),
+            done: false,
+        })
+    }
+
+    /// Position in the input of the next chunk to read.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn read_chunk(&mut self) -> Result<Option<Chunk>> {
+        let offset = self.offset;
+
+        // an input ending right before a chunk is a normal end
+        let mut length_bytes: [u8; 4] = [0; 4];
+        let read = read_full(&mut self.reader, &mut length_bytes)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        if read < length_bytes.len() {
+            return Err(PngSecretError::TruncatedChunk { offset });
+        }
+        let length = u32::from_be_bytes(length_bytes) as u64;
+
+        let mut chunk_type_data: [u8; 4] = [0; 4];
+        self.read_exact(&mut chunk_type_data, offset)?;
+
+        // the length comes from the input, `take` makes the buffer grow with
+        // the bytes really read instead of allocating it up front
+        let mut data = Vec::new();
+        (&mut self.reader).take(length).read_to_end(&mut data)?;
+        if (data.len() as u64) < length {
+            return Err(PngSecretError::TruncatedChunk { offset });
+        }
+
+        let mut crc_data: [u8; 4] = [0; 4];
+        self.read_exact(&mut crc_data, offset)?;
+        let crc = u32::from_be_bytes(crc_data);
+
+        let chunk = Chunk::from_parts(chunk_type_data, data, crc)
+            .map_err(|e| e.at_offset(offset))?;
+
+        self.offset += 12 + chunk.length() as usize;
+
+        Ok(Some(chunk))
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8], offset: usize) -> Result<()> {
+        if read_full(&mut self.reader, buf)? < buf.len() {
+            return Err(PngSecretError::TruncatedChunk { offset });
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for ChunkReader<R> {
+    type Item = Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.read_chunk() {
+            Ok(Some(chunk)) => Some(Ok(chunk)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+// like `read_exact` but returns how many bytes were read before the end
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(read)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn testing_chunks() -> Vec<Chunk> {
+        vec![
+            Chunk::new(ChunkType::from_str("FrSt").unwrap(), b"I am the first chunk".to_vec()),
+            Chunk::new(ChunkType::from_str("miDl").unwrap(), b"I am another chunk".to_vec()),
+            Chunk::new(ChunkType::from_str("LASt").unwrap(), b"I am the last chunk".to_vec()),
+        ]
+    }
+
+    fn testing_bytes() -> Vec<u8> {
+        Png::from_chunks(testing_chunks()).as_bytes()
+    }
+
+    // reader giving at most one byte per call, like a slow network stream
+    struct OneByte<'a>(&'a [u8]);
+
+    impl Read for OneByte<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.0.take(1).read(buf).inspect(|&n| self.0 = &self.0[n..])
+        }
+    }
+
+    #[test]
+    fn test_read_chunks() {
+        let bytes = testing_bytes();
+        let reader = ChunkReader::new(bytes.as_slice()).unwrap();
+        let chunks: Vec<Chunk> = reader.collect::<Result<_>>().unwrap();
+        assert_eq!(chunks, testing_chunks());
+    }
+
+    #[test]
+    fn test_read_chunks_one_byte_at_a_time() {
+        let bytes = testing_bytes();
+        let reader = ChunkReader::new(OneByte(&bytes)).unwrap();
+        let chunks: Vec<Chunk> = reader.collect::<Result<_>>().unwrap();
+        assert_eq!(chunks, testing_chunks());
+    }
+
+    #[test]
+    fn test_offset() {
+        let bytes = testing_bytes();
+        let mut reader = ChunkReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.offset(), 8);
+        let chunk = reader.next().unwrap().unwrap();
+        assert_eq!(reader.offset(), 8 + chunk.as_bytes().len());
+    }
+
+    #[test]
+    fn test_invalid_signature() {
+        let mut bytes = testing_bytes();
+        bytes[0] = 13;
+        let reader = ChunkReader::new(bytes.as_slice());
+        assert!(matches!(reader, Err(PngSecretError::InvalidSignature)));
+
+        let reader = ChunkReader::new(&bytes[..4]);
+        assert!(matches!(reader, Err(PngSecretError::InvalidSignature)));
+    }
+
+    #[test]
+    fn test_truncated_input() {
+        let bytes = testing_bytes();
+        let first_chunk_length = testing_chunks()[0].as_bytes().len();
+
+        for length in 9..8 + first_chunk_length {
+            let mut reader = ChunkReader::new(&bytes[..length]).unwrap();
+            let chunk = reader.next().unwrap();
+            assert!(matches!(chunk, Err(PngSecretError::TruncatedChunk { offset: 8 })));
+            assert!(reader.next().is_none());
+        }
+    }
+
+    #[test]
+    fn test_chunk_length_too_big() {
+        let mut bytes = testing_bytes();
+        bytes[8..12].copy_from_slice(&[255, 255, 255, 255]);
+        let mut reader = ChunkReader::new(bytes.as_slice()).unwrap();
+        assert!(matches!(reader.next(), Some(Err(PngSecretError::TruncatedChunk { .. }))));
+    }
+
+    #[test]
+    fn test_crc_mismatch_offset() {
+        let mut bytes = testing_bytes();
+        let second_chunk = 8 + testing_chunks()[0].as_bytes().len();
+        bytes[second_chunk + 8] ^= 0xff;
+
+        let mut reader = ChunkReader::new(bytes.as_slice()).unwrap();
+        assert!(reader.next().unwrap().is_ok());
+        match reader.next() {
+            Some(Err(PngSecretError::CrcMismatch { offset, .. })) => assert_eq!(offset, second_chunk),
+            _ => panic!("expected a CRC mismatch"),
+        }
+    }
+}
@@ -0,0 +1,240 @@
+//! X25519 key pairs to encrypt secrets for other people, without sharing a
+//! passphrase.
+//!
+//! A secret is encrypted with a random file key, and the file key is wrapped
+//! once for each recipient : an ephemeral key pair is generated, the wrapping
+//! key is derived with HKDF-SHA256 from the shared secret of the ephemeral
+//! private key and the recipient public key, and the file key is sealed with
+//! ChaCha20-Poly1305. The identity of the recipient can redo the exchange
+//! with the ephemeral public key and unwrap the file key.
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use chacha20poly1305::aead::{Aead, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, KeyInit, Nonce};
+use hkdf::Hkdf;
+use sha2::{Digest, Sha256};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::{PngSecretError, Result};
+
+const PUBLIC_PREFIX: &str = "png-secret-pub-";
+const SECRET_PREFIX: &str = "PNG-SECRET-KEY-";
+const HKDF_INFO: &[u8] = b"png-secret x25519";
+
+pub(crate) const FILE_KEY_LENGTH: usize = 32;
+const FINGERPRINT_LENGTH: usize = 8;
+// fingerprint, ephemeral public key and the wrapped file key with its tag
+pub(crate) const STANZA_LENGTH: usize = FINGERPRINT_LENGTH + 32 + FILE_KEY_LENGTH + 16;
+
+/// A private key, kept by the person the secrets are encrypted for.
+///
+/// Written as `PNG-SECRET-KEY-` followed by 64 hex digits.
+#[derive(Clone)]
+pub struct Identity(StaticSecret);
+
+impl Identity {
+    pub fn generate() -> Identity {
+        Identity(StaticSecret::random_from_rng(OsRng))
+    }
+
+    /// The public key to give to the people encrypting secrets.
+    pub fn recipient(&self) -> Recipient {
+        Recipient(PublicKey::from(&self.0))
+    }
+
+    /// Read the identity of an identity file, comment lines start with `#`.
+    pub fn from_file_content(content: &str) -> Result<Identity> {
+        let mut keys = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+        match (keys.next(), keys.next()) {
+            (Some(key), None) => key.parse(),
+            _ => Err(PngSecretError::InvalidKey("an identity file holds exactly one key".to_string())),
+        }
+    }
+
+    /// The content of an identity file, with the public key in a comment.
+    pub fn to_file_content(&self) -> String {
+        format!("# public key: {}\n{}\n", self.recipient(), self)
+    }
+
+    // the file key wrapped in `stanza`, if it was wrapped for this identity
+    pub(crate) fn unwrap_file_key(&self, stanza: &[u8]) -> Option<[u8; FILE_KEY_LENGTH]> {
+        let recipient = self.recipient();
+        let (fingerprint, rest) = stanza.split_at(FINGERPRINT_LENGTH);
+        let (ephemeral, wrapped) = rest.split_at(32);
+        if fingerprint != recipient.fingerprint_bytes() {
+            return None;
+        }
+
+        let ephemeral = PublicKey::from(<[u8; 32]>::try_from(ephemeral).ok()?);
+        let shared = self.0.diffie_hellman(&ephemeral);
+        let file_key = wrapping_cipher(shared.as_bytes(), &ephemeral, &recipient.0)
+            .decrypt(Nonce::from_slice(&[0; 12]), wrapped)
+            .ok()?;
+        file_key.try_into().ok()
+    }
+}
+
+impl FromStr for Identity {
+    type Err = PngSecretError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let hex = s
+            .strip_prefix(SECRET_PREFIX)
+            .ok_or_else(|| PngSecretError::InvalidKey(format!("an identity starts with '{}'", SECRET_PREFIX)))?;
+        Ok(Identity(StaticSecret::from(decode_key(hex)?)))
+    }
+}
+
+impl Display for Identity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", SECRET_PREFIX, encode_hex(self.0.as_bytes()).to_uppercase())
+    }
+}
+
+// don't print the private key in logs
+impl std::fmt::Debug for Identity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Identity({})", self.recipient())
+    }
+}
+
+/// A public key secrets can be encrypted for.
+///
+/// Written as `png-secret-pub-` followed by 64 hex digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Recipient(PublicKey);
+
+impl Recipient {
+    /// Short hex name of the key, stored in front of each wrapped file key.
+    pub fn fingerprint(&self) -> String {
+        encode_hex(&self.fingerprint_bytes())
+    }
+
+    fn fingerprint_bytes(&self) -> [u8; FINGERPRINT_LENGTH] {
+        let hash = Sha256::digest(self.0.as_bytes());
+        hash[..FINGERPRINT_LENGTH].try_into().unwrap()
+    }
+
+    // wrap `file_key` for this recipient, with a fresh ephemeral key
+    pub(crate) fn wrap_file_key(&self, file_key: &[u8; FILE_KEY_LENGTH]) -> Vec<u8> {
+        let ephemeral_secret = StaticSecret::random_from_rng(OsRng);
+        let ephemeral = PublicKey::from(&ephemeral_secret);
+        let shared = ephemeral_secret.diffie_hellman(&self.0);
+
+        // the wrapping key is only used once, a zero nonce is fine
+        let wrapped = wrapping_cipher(shared.as_bytes(), &ephemeral, &self.0)
+            .encrypt(Nonce::from_slice(&[0; 12]), file_key.as_slice())
+            .expect("a 32 bytes message fits");
+
+        let mut stanza = Vec::with_capacity(STANZA_LENGTH);
+        stanza.extend_from_slice(&self.fingerprint_bytes());
+        stanza.extend_from_slice(ephemeral.as_bytes());
+        stanza.extend(wrapped);
+        stanza
+    }
+}
+
+impl FromStr for Recipient {
+    type Err = PngSecretError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let hex = s
+            .strip_prefix(PUBLIC_PREFIX)
+            .ok_or_else(|| PngSecretError::InvalidKey(format!("a public key starts with '{}'", PUBLIC_PREFIX)))?;
+        Ok(Recipient(PublicKey::from(decode_key(hex)?)))
+    }
+}
+
+impl Display for Recipient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", PUBLIC_PREFIX, encode_hex(self.0.as_bytes()))
+    }
+}
+
+/// The fingerprint of the recipient a wrapped file key is for.
+pub(crate) fn stanza_fingerprint(stanza: &[u8]) -> String {
+    encode_hex(&stanza[..FINGERPRINT_LENGTH])
+}
+
+// both public keys go in the salt, the wrapped key is bound to this exchange
+fn wrapping_cipher(shared: &[u8; 32], ephemeral: &PublicKey, recipient: &PublicKey) -> ChaCha20Poly1305 {
+    let salt = [ephemeral.as_bytes().as_slice(), recipient.as_bytes()].concat();
+    let mut key = [0; 32];
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(HKDF_INFO, &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 length");
+    ChaCha20Poly1305::new(&key.into())
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_key(hex: &str) -> Result<[u8; 32]> {
+    let invalid = || PngSecretError::InvalidKey("a key is 64 hex digits".to_string());
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err(invalid());
+    }
+
+    let mut key = [0; 32];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_strings() {
+        let identity = Identity::generate();
+        let recipient = identity.recipient();
+
+        let parsed: Identity = identity.to_string().parse().unwrap();
+        assert_eq!(parsed.recipient(), recipient);
+        assert_eq!(recipient.to_string().parse::<Recipient>().unwrap(), recipient);
+        assert_eq!(recipient.fingerprint().len(), 16);
+
+        assert!("png-secret-pub-1234".parse::<Recipient>().is_err());
+        assert!(format!("png-secret-pub-{}", "zz".repeat(32)).parse::<Recipient>().is_err());
+        assert!(recipient.to_string().parse::<Identity>().is_err());
+    }
+
+    #[test]
+    fn test_identity_file() {
+        let identity = Identity::generate();
+        let content = identity.to_file_content();
+        assert!(content.starts_with("# public key: png-secret-pub-"));
+
+        let parsed = Identity::from_file_content(&content).unwrap();
+        assert_eq!(parsed.recipient(), identity.recipient());
+
+        assert!(Identity::from_file_content("# nothing\n").is_err());
+        assert!(Identity::from_file_content(&format!("{}{}", content, content)).is_err());
+    }
+
+    #[test]
+    fn test_wrap_unwrap() {
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+        let file_key = [7; FILE_KEY_LENGTH];
+
+        let stanza = alice.recipient().wrap_file_key(&file_key);
+        assert_eq!(stanza.len(), STANZA_LENGTH);
+        assert_eq!(stanza_fingerprint(&stanza), alice.recipient().fingerprint());
+        assert_eq!(alice.unwrap_file_key(&stanza), Some(file_key));
+        assert_eq!(bob.unwrap_file_key(&stanza), None);
+
+        let mut tampered = stanza.clone();
+        tampered[STANZA_LENGTH - 1] ^= 1;
+        assert_eq!(alice.unwrap_file_key(&tampered), None);
+    }
+}
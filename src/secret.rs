@@ -0,0 +1,889 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::attachment::Attachment;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::crypto;
+use crate::envelope::{ContentType, Envelope};
+use crate::lsb::{self, LsbOptions};
+use crate::png::Png;
+use crate::reader::ChunkReader;
+use crate::split::{self, Part, Reassembled};
+use crate::text::{IText, Text, TextChunk, ZText};
+use crate::writer::PngWriter;
+use crate::zlib;
+use crate::{PngSecretError, Result};
+
+/// Where a new secret chunk goes in the png.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Position {
+    /// Right before `IEND`. Decoders and optimizers can drop chunks after it.
+    #[default]
+    BeforeIend,
+    /// Right before the first `IDAT`, falls back to before `IEND`.
+    BeforeIdat,
+}
+
+impl Position {
+    // chunk types the secret can be inserted before, by preference
+    fn anchors(&self) -> &'static [&'static str] {
+        match self {
+            Position::BeforeIend => &["IEND"],
+            Position::BeforeIdat => &["IDAT", "IEND"],
+        }
+    }
+}
+
+/// What to do with the chunks already holding a secret of the same type.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnExisting {
+    /// Keep them, the new secret is added after them.
+    #[default]
+    Append,
+    /// Remove them.
+    Replace,
+    /// Fail with [`PngSecretError::ChunkExists`].
+    Fail,
+}
+
+/// Hide `message` in a new chunk of type `chunk_type`, right before `IEND`.
+pub fn encode(png: &mut Png, chunk_type: &str, message: impl Into<Envelope>) -> Result<()> {
+    encode_at(png, chunk_type, message, Position::default())
+}
+
+/// Hide `message` in a new chunk of type `chunk_type` at `position`.
+///
+/// The chunk is appended if the png has neither `IDAT` nor `IEND`.
+pub fn encode_at(png: &mut Png, chunk_type: &str, message: impl Into<Envelope>, position: Position) -> Result<()> {
+    encode_with(png, chunk_type, message, position, OnExisting::Append)
+}
+
+/// Hide `message` in a new chunk of type `chunk_type` at `position`, the
+/// chunks already there with this type are handled as told by `on_existing`.
+pub fn encode_with(
+    png: &mut Png,
+    chunk_type: &str,
+    message: impl Into<Envelope>,
+    position: Position,
+    on_existing: OnExisting,
+) -> Result<()> {
+    encode_chunks(png, vec![secret_chunk(chunk_type, message)?], position, on_existing)
+}
+
+/// Add `chunks` in order at `position`.
+///
+/// The chunks already there with the type of `chunks` are handled as told
+/// by `on_existing`.
+pub fn encode_chunks(png: &mut Png, chunks: Vec<Chunk>, position: Position, on_existing: OnExisting) -> Result<()> {
+    if let Some(secret_type) = chunks.first().map(|chunk| chunk.chunk_type().to_string()) {
+        let existing = png.chunks_by_type(&secret_type).next().is_some();
+        match on_existing {
+            OnExisting::Replace if existing => {
+                let kept = png.chunks()
+                    .iter()
+                    .filter(|chunk| chunk.chunk_type().to_string() != secret_type)
+                    .cloned()
+                    .collect();
+                *png = Png::from_chunks(kept);
+            }
+            OnExisting::Fail if existing => return Err(PngSecretError::ChunkExists(secret_type)),
+            _ => {}
+        }
+    }
+    for chunk in chunks {
+        insert_at(png, chunk, position)?;
+    }
+    Ok(())
+}
+
+/// Hide `message` in chunks of type `chunk_type` at `position`, each one
+/// holding at most `part_size` bytes of it. See [`crate::split`].
+pub fn encode_split(
+    png: &mut Png,
+    chunk_type: &str,
+    message: impl Into<Envelope>,
+    part_size: usize,
+    position: Position,
+) -> Result<()> {
+    encode_chunks(png, split_chunks(chunk_type, message, part_size)?, position, OnExisting::Append)
+}
+
+/// Build a chunk of type `chunk_type` holding `message` in its envelope.
+pub fn secret_chunk(chunk_type: &str, message: impl Into<Envelope>) -> Result<Chunk> {
+    Ok(Chunk::new(ChunkType::from_str(chunk_type)?, message.into().as_bytes()?))
+}
+
+/// Build the chunks of type `chunk_type` holding the parts of `message`,
+/// at most `part_size` bytes each.
+pub fn split_chunks(chunk_type: &str, message: impl Into<Envelope>, part_size: usize) -> Result<Vec<Chunk>> {
+    let chunk_type = ChunkType::from_str(chunk_type)?;
+    let parts = split::split(&message.into(), part_size)?;
+    parts.iter().map(|part| Ok(Chunk::new(chunk_type.clone(), part.as_bytes()?))).collect()
+}
+
+/// Hide `message` in a standard text chunk of type `chunk_type`, under
+/// `keyword`. Viewers and optimizers keep these chunks.
+pub fn encode_text(png: &mut Png, chunk_type: &str, keyword: &str, message: &str, position: Position) -> Result<()> {
+    insert_at(png, text_chunk(chunk_type, keyword, message)?, position)
+}
+
+/// Hide `message` in the pixels of the png, tagged with `chunk_type`.
+///
+/// The image data is decoded and written back, its other chunks are kept.
+pub fn encode_lsb(png: &mut Png, chunk_type: &str, message: impl Into<Envelope>, options: &LsbOptions) -> Result<()> {
+    let tag = ChunkType::from_str(chunk_type)?.bytes();
+    let mut image = png.image()?;
+    lsb::embed(&mut image, tag, &message.into().as_bytes()?, options)?;
+    png.set_image(&image);
+    Ok(())
+}
+
+/// Build a text chunk of type `chunk_type`, `tEXt`, `zTXt` or `iTXt`.
+pub fn text_chunk(chunk_type: &str, keyword: &str, message: &str) -> Result<Chunk> {
+    match chunk_type {
+        "tEXt" => Ok(Text::new(keyword, message)?.into()),
+        "zTXt" => Ok(ZText::new(keyword, message)?.into()),
+        "iTXt" => Ok(IText::new(keyword, message)?.into()),
+        _ => Err(PngSecretError::InvalidChunkType(chunk_type.to_string())),
+    }
+}
+
+fn insert_at(png: &mut Png, chunk: Chunk, position: Position) -> Result<()> {
+    let anchor = position.anchors().iter().find(|anchor| png.chunk_by_type(anchor).is_some());
+    match anchor {
+        Some(anchor) => png.insert_before(anchor, chunk),
+        None => {
+            png.append_chunk(chunk);
+            Ok(())
+        }
+    }
+}
+
+/// Read the message stored in the first chunk of type `chunk_type`.
+///
+/// Returns `Ok(None)` if the png has no chunk of this type, and fails with
+/// [`PngSecretError::KeyNeeded`] if the message is encrypted.
+pub fn decode(png: &Png, chunk_type: &str) -> Result<Option<String>> {
+    decode_envelope(png, chunk_type)?.map(read_text).transpose()
+}
+
+/// Read the envelope stored in the first chunk of type `chunk_type`. A
+/// split secret is put back together from all its parts.
+pub fn decode_envelope(png: &Png, chunk_type: &str) -> Result<Option<Envelope>> {
+    Ok(decode_reassembled(png, chunk_type)?.map(|reassembled| reassembled.envelope))
+}
+
+/// Same as [`decode_envelope`], telling how many parts were joined.
+pub fn decode_reassembled(png: &Png, chunk_type: &str) -> Result<Option<Reassembled>> {
+    decode_all(png, chunk_type)?.into_iter().next().transpose()
+}
+
+/// Read every secret stored in chunks of type `chunk_type`, in order.
+///
+/// A split secret counts once, at the position of its first part. Each
+/// secret is read on its own, a broken one doesn't hide the others.
+pub fn decode_all(png: &Png, chunk_type: &str) -> Result<Vec<Result<Reassembled>>> {
+    ChunkType::from_str(chunk_type)?;
+    let envelopes: Vec<Result<Envelope>> = png.chunks_by_type(chunk_type)
+        .map(|chunk| Envelope::read(chunk.data()))
+        .collect();
+
+    let mut secrets = Vec::new();
+    let mut joined = Vec::new();
+    for (index, envelope) in envelopes.iter().enumerate() {
+        let envelope = match envelope {
+            Ok(envelope) => envelope,
+            Err(e) => {
+                secrets.push(Err(PngSecretError::InvalidEnvelope(e.to_string())));
+                continue;
+            }
+        };
+
+        match Part::of(envelope) {
+            Some(part) if joined.contains(&part.payload_id) => {}
+            Some(part) => {
+                joined.push(part.payload_id);
+                // the other chunks of this type can hold anything, only parts are kept
+                let parts: Vec<Envelope> = envelopes[index..].iter().flatten().cloned().collect();
+                secrets.push(split::join(&parts));
+            }
+            None => secrets.push(split::join(std::slice::from_ref(envelope))),
+        }
+    }
+    Ok(secrets)
+}
+
+/// Read the text stored under `keyword` in the first text chunk of type
+/// `chunk_type` with this keyword.
+///
+/// Returns `Ok(None)` if the png has no such text chunk.
+pub fn decode_text(png: &Png, chunk_type: &str, keyword: &str) -> Result<Option<String>> {
+    ChunkType::from_str(chunk_type)?;
+
+    let text = png.chunks_by_type(chunk_type)
+        .filter_map(|chunk| TextChunk::try_from(chunk).ok())
+        .find(|text| text.keyword() == keyword);
+
+    text.map(|text| text.text()).transpose()
+}
+
+/// Read the message hidden in the pixels with [`encode_lsb`].
+///
+/// Returns `Ok(None)` if no message tagged with `chunk_type` is found.
+pub fn decode_lsb(png: &Png, chunk_type: &str, options: &LsbOptions) -> Result<Option<String>> {
+    decode_lsb_envelope(png, chunk_type, options)?.map(read_text).transpose()
+}
+
+// the text of a secret that isn't encrypted, decompressed if needed
+fn read_text(envelope: Envelope) -> Result<String> {
+    envelope.decompress(zlib::DEFAULT_LIMIT)?.text()
+}
+
+/// Read the envelope hidden in the pixels with [`encode_lsb`].
+pub fn decode_lsb_envelope(png: &Png, chunk_type: &str, options: &LsbOptions) -> Result<Option<Envelope>> {
+    let tag = ChunkType::from_str(chunk_type)?.bytes();
+    let data = lsb::extract(&png.image()?, tag, options)?;
+    data.map(|data| Envelope::read(&data)).transpose()
+}
+
+/// Remove the first chunk of type `chunk_type` and return it.
+///
+/// If it is a part of a split secret, the other parts are removed too.
+pub fn remove(png: &mut Png, chunk_type: &str) -> Result<Chunk> {
+    let removed = png.remove_chunk(chunk_type)?;
+
+    if let Some(part) = part_of(&removed) {
+        let chunks = png.chunks()
+            .iter()
+            .filter(|chunk| !(chunk.chunk_type() == removed.chunk_type() && same_secret(chunk, &part)))
+            .cloned()
+            .collect();
+        *png = Png::from_chunks(chunks);
+    }
+    Ok(removed)
+}
+
+// the part header of a chunk holding a part of a split secret
+fn part_of(chunk: &Chunk) -> Option<Part> {
+    Envelope::try_from(chunk.data()).ok().as_ref().and_then(Part::of)
+}
+
+fn same_secret(chunk: &Chunk, part: &Part) -> bool {
+    part_of(chunk).is_some_and(|other| other.payload_id == part.payload_id)
+}
+
+/// Copy the png from `input` to `output` one chunk at a time, adding
+/// `message` in a new chunk of type `chunk_type` at `position`.
+pub fn encode_stream<R: Read, W: Write>(
+    input: R,
+    output: W,
+    chunk_type: &str,
+    message: impl Into<Envelope>,
+    position: Position,
+) -> Result<W> {
+    encode_chunk_stream(input, output, secret_chunk(chunk_type, message)?, position)
+}
+
+/// Copy the png from `input` to `output` one chunk at a time, adding `chunk`
+/// at `position`.
+pub fn encode_chunk_stream<R: Read, W: Write>(
+    input: R,
+    output: W,
+    chunk: Chunk,
+    position: Position,
+) -> Result<W> {
+    encode_chunks_stream(input, output, vec![chunk], position, OnExisting::Append)
+}
+
+/// Copy the png from `input` to `output` one chunk at a time, adding
+/// `chunks` in order at `position`.
+///
+/// The chunks already there with the type of `chunks` are handled as told
+/// by `on_existing`.
+pub fn encode_chunks_stream<R: Read, W: Write>(
+    input: R,
+    output: W,
+    chunks: Vec<Chunk>,
+    position: Position,
+    on_existing: OnExisting,
+) -> Result<W> {
+    let mut writer = PngWriter::new(output)?;
+    let secret_type = chunks.first().map(|chunk| chunk.chunk_type().clone());
+    let mut secrets = Some(chunks);
+
+    let iend = copy_until_end(ChunkReader::new(input)?, &mut writer, |chunk, writer| {
+        if Some(chunk.chunk_type()) == secret_type.as_ref() {
+            match on_existing {
+                OnExisting::Append => {}
+                OnExisting::Replace => return Ok(()),
+                OnExisting::Fail => return Err(PngSecretError::ChunkExists(chunk.chunk_type().to_string())),
+            }
+        }
+        if position == Position::BeforeIdat && chunk.chunk_type().to_string() == "IDAT" {
+            for secret in secrets.take().unwrap_or_default() {
+                writer.write_chunk(&secret)?;
+            }
+        }
+        writer.write_chunk(&chunk)
+    })?;
+
+    for secret in secrets.unwrap_or_default() {
+        writer.write_chunk(&secret)?;
+    }
+    if let Some(iend) = iend {
+        writer.write_chunk(&iend)?;
+    }
+
+    writer.finish()
+}
+
+/// Copy the png from `input` to `output` one chunk at a time, without the
+/// first chunk of type `chunk_type`, and return the removed chunk.
+///
+/// If it is a part of a split secret, the other parts are removed too.
+pub fn remove_stream<R: Read, W: Write>(input: R, output: W, chunk_type: &str) -> Result<(Chunk, W)> {
+    let find_chunk_type = ChunkType::from_str(chunk_type)?;
+    let mut writer = PngWriter::new(output)?;
+    let mut removed = None;
+    let mut removed_part = None;
+
+    let iend = copy_until_end(ChunkReader::new(input)?, &mut writer, |chunk, writer| {
+        if chunk.chunk_type().eq(&find_chunk_type) {
+            if removed.is_none() {
+                removed_part = part_of(&chunk);
+                removed = Some(chunk);
+                return Ok(());
+            }
+            if removed_part.is_some_and(|part| same_secret(&chunk, &part)) {
+                return Ok(());
+            }
+        }
+        writer.write_chunk(&chunk)
+    })?;
+
+    if let Some(iend) = iend {
+        writer.write_chunk(&iend)?;
+    }
+
+    match removed {
+        Some(chunk) => Ok((chunk, writer.finish()?)),
+        None => Err(PngSecretError::ChunkNotFound(chunk_type.to_string())),
+    }
+}
+
+// give every chunk except `IEND` to `write`, `IEND` is returned so the
+// caller can still add chunks before it. Older files can have secrets after
+// `IEND`, they are moved before it.
+fn copy_until_end<R: Read, W: Write>(
+    reader: ChunkReader<R>,
+    writer: &mut PngWriter<W>,
+    mut write: impl FnMut(Chunk, &mut PngWriter<W>) -> Result<()>,
+) -> Result<Option<Chunk>> {
+    let mut iend = None;
+
+    for chunk in reader {
+        let chunk = chunk?;
+        if chunk.chunk_type().to_string() == "IEND" {
+            iend = Some(chunk);
+        } else {
+            write(chunk, writer)?;
+        }
+    }
+
+    Ok(iend)
+}
+
+/// Rewrite the png at `path` with `rewrite`, which copies it from a reader
+/// to a writer, usually with one of the `_stream` functions.
+///
+/// The result goes to `output` if given, else to a temporary file replacing
+/// `path` once everything is written. Nothing is left behind on error.
+pub fn rewrite_file<T>(
+    path: &Path,
+    output: Option<&Path>,
+    rewrite: impl FnOnce(BufReader<File>, BufWriter<File>) -> Result<T>,
+) -> Result<T> {
+    let input = BufReader::new(File::open(path)?);
+
+    let (written_path, written) = match output {
+        Some(output) => (output.to_path_buf(), File::create_new(output)?),
+        None => create_temporary(path)?,
+    };
+
+    let result = rewrite(input, BufWriter::new(written)).and_then(|res| {
+        if output.is_none() {
+            // the temporary file replaces the png, it keeps its permissions
+            fs::set_permissions(&written_path, fs::metadata(path)?.permissions())?;
+            fs::rename(&written_path, path)?;
+        }
+        Ok(res)
+    });
+    if result.is_err() {
+        // the rewrite error matters more than a failed cleanup
+        let _ = fs::remove_file(&written_path);
+    }
+    result
+}
+
+// a new file next to `path`, never one left there by a crash or someone else
+fn create_temporary(path: &Path) -> Result<(PathBuf, File)> {
+    let mut attempt = 0;
+    loop {
+        let tmp_path = path.with_extension(format!("png-secret.{}.{}.tmp", std::process::id(), attempt));
+        match File::create_new(&tmp_path) {
+            Ok(file) => return Ok((tmp_path, file)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// What a secret chunk holds, as far as it can be told without a key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretInfo {
+    /// One part of a split secret.
+    Part(Part),
+    Encrypted,
+    File(Attachment),
+    Text(String),
+    /// Data that isn't text, its length in bytes.
+    Binary(usize),
+}
+
+/// Tell what the secret in `chunk`, one of [`list`], holds. It is
+/// decompressed if needed.
+pub fn describe(chunk: &Chunk) -> Result<SecretInfo> {
+    let envelope = Envelope::read(chunk.data())?;
+    if envelope.flags.split {
+        return Part::of(&envelope)
+            .map(SecretInfo::Part)
+            .ok_or_else(|| PngSecretError::InvalidEnvelope("truncated part header".to_string()));
+    }
+    if envelope.flags.encrypted {
+        return Ok(SecretInfo::Encrypted);
+    }
+
+    let envelope = envelope.decompress(zlib::DEFAULT_LIMIT)?;
+    if envelope.content_type == ContentType::File {
+        return envelope.attachment().map(SecretInfo::File);
+    }
+    match envelope.text() {
+        Ok(text) => Ok(SecretInfo::Text(text)),
+        Err(_) => Ok(SecretInfo::Binary(envelope.payload.len())),
+    }
+}
+
+/// List every readable text chunk.
+pub fn list_text(png: &Png) -> Vec<TextChunk> {
+    png.chunks()
+        .iter()
+        .filter(|chunk| TextChunk::is_text_chunk(chunk.chunk_type()))
+        .filter_map(|chunk| TextChunk::try_from(chunk).ok())
+        .collect()
+}
+
+/// List every chunk holding a secret message.
+///
+/// Standard chunks are part of a normal image and never hold a secret.
+/// Secrets written before the envelope are recognized from their content,
+/// text or encrypted data.
+pub fn list(png: &Png) -> Vec<&Chunk> {
+    png.chunks()
+        .iter()
+        .filter(|chunk| !chunk.chunk_type().is_standard())
+        .filter(|chunk| {
+            let data = chunk.data();
+            Envelope::is_envelope(data) || crypto::is_encrypted(data) || std::str::from_utf8(data).is_ok()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::Compression;
+    use crate::image::Image;
+
+    fn testing_png() -> Png {
+        let chunks = vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0, 0, 0, 200, 0, 0, 0, 200, 8, 6, 0, 0, 0]),
+            Chunk::new(ChunkType::from_str("sBIT").unwrap(), vec![8]),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), vec![0xff]),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+        ];
+        Png::from_chunks(chunks)
+    }
+
+    // `testing_png` with real pixels
+    fn image_png() -> Png {
+        let mut png = testing_png();
+        let ihdr = png.ihdr().unwrap();
+        let pixels = (0..200 * 200 * 4).map(|i| (i % 251) as u8).collect();
+        png.set_image(&Image::new(ihdr, pixels).unwrap());
+        png
+    }
+
+    fn chunk_types(png: &Png) -> Vec<String> {
+        png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect()
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let mut png = testing_png();
+        encode(&mut png, "RuSt", "Secret message").unwrap();
+        assert_eq!(decode(&png, "RuSt").unwrap(), Some("Secret message".to_string()));
+        assert_eq!(chunk_types(&png), vec!["IHDR", "sBIT", "IDAT", "RuSt", "IEND"]);
+        assert!(Envelope::is_envelope(png.chunk_by_type("RuSt").unwrap().data()));
+    }
+
+    #[test]
+    fn test_encode_compressed() {
+        let text = "A secret that repeats itself. ".repeat(10);
+        let envelope = Envelope::from(text.as_str()).compress(Compression::Deflate).unwrap();
+
+        let mut png = image_png();
+        encode(&mut png, "RuSt", envelope.clone()).unwrap();
+        encode_lsb(&mut png, "RuSt", envelope, &LsbOptions::default()).unwrap();
+        assert_eq!(decode(&png, "RuSt").unwrap(), Some(text.clone()));
+        assert_eq!(decode_lsb(&png, "RuSt", &LsbOptions::default()).unwrap(), Some(text));
+    }
+
+    #[test]
+    fn test_decode_legacy() {
+        // secrets written before the envelope are only the message
+        let mut png = testing_png();
+        png.insert_before("IEND", Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"Old message".to_vec())).unwrap();
+        assert_eq!(decode(&png, "RuSt").unwrap(), Some("Old message".to_string()));
+    }
+
+    #[test]
+    fn test_decode_encrypted() {
+        let identity = crate::recipient::Identity::generate();
+        let envelope = Envelope::from("Secret message").encrypt_to(&[identity.recipient()]).unwrap();
+
+        let mut png = testing_png();
+        encode(&mut png, "RuSt", envelope).unwrap();
+        assert!(matches!(decode(&png, "RuSt"), Err(PngSecretError::KeyNeeded)));
+
+        let envelope = decode_envelope(&png, "RuSt").unwrap().unwrap();
+        assert_eq!(envelope.decrypt_with(&identity).unwrap().text().unwrap(), "Secret message");
+    }
+
+    #[test]
+    fn test_encode_split() {
+        let mut png = testing_png();
+        encode_split(&mut png, "RuSt", "A secret too long for one chunk", 10, Position::BeforeIend).unwrap();
+        assert_eq!(chunk_types(&png), vec!["IHDR", "sBIT", "IDAT", "RuSt", "RuSt", "RuSt", "RuSt", "IEND"]);
+
+        let reassembled = decode_reassembled(&png, "RuSt").unwrap().unwrap();
+        assert_eq!(reassembled.envelope.text().unwrap(), "A secret too long for one chunk");
+        assert_eq!(reassembled.parts, 4);
+        assert_eq!(decode(&png, "RuSt").unwrap(), Some("A secret too long for one chunk".to_string()));
+
+        remove(&mut png, "RuSt").unwrap();
+        assert_eq!(chunk_types(&png), vec!["IHDR", "sBIT", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_decode_split_missing() {
+        let mut png = testing_png();
+        encode_split(&mut png, "RuSt", "A secret too long for one chunk", 10, Position::BeforeIend).unwrap();
+        let mut chunks = png.chunks().to_vec();
+        chunks.remove(5);
+
+        let png = Png::from_chunks(chunks);
+        assert!(matches!(decode(&png, "RuSt"), Err(PngSecretError::MissingParts { total: 4, .. })));
+    }
+
+    #[test]
+    fn test_split_stream() {
+        let chunks = split_chunks("RuSt", "A secret too long for one chunk", 10).unwrap();
+        let input = testing_png().as_bytes();
+        let output = encode_chunks_stream(input.as_slice(), Vec::new(), chunks, Position::BeforeIdat, OnExisting::Append).unwrap();
+
+        let png = Png::try_from(output.as_ref()).unwrap();
+        assert_eq!(chunk_types(&png), vec!["IHDR", "sBIT", "RuSt", "RuSt", "RuSt", "RuSt", "IDAT", "IEND"]);
+        assert_eq!(decode(&png, "RuSt").unwrap(), Some("A secret too long for one chunk".to_string()));
+
+        let (_, output) = remove_stream(output.as_slice(), Vec::new(), "RuSt").unwrap();
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn test_decode_all() {
+        let mut png = testing_png();
+        encode(&mut png, "RuSt", "First secret").unwrap();
+        encode_split(&mut png, "RuSt", "A secret too long for one chunk", 10, Position::BeforeIend).unwrap();
+        encode(&mut png, "RuSt", "Last secret").unwrap();
+
+        let secrets: Vec<String> = decode_all(&png, "RuSt")
+            .unwrap()
+            .into_iter()
+            .map(|secret| secret.unwrap().envelope.text().unwrap())
+            .collect();
+        assert_eq!(secrets, ["First secret", "A secret too long for one chunk", "Last secret"]);
+        assert_eq!(decode(&png, "RuSt").unwrap(), Some("First secret".to_string()));
+        assert!(decode_all(&png, "okKo").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_encode_on_existing() {
+        let mut png = testing_png();
+        encode(&mut png, "RuSt", "Old secret").unwrap();
+        let input = png.as_bytes();
+        let chunks = || vec![secret_chunk("RuSt", "New secret").unwrap()];
+
+        let output = encode_chunks_stream(input.as_slice(), Vec::new(), chunks(), Position::BeforeIend, OnExisting::Append).unwrap();
+        let png = Png::try_from(output.as_ref()).unwrap();
+        assert_eq!(decode_all(&png, "RuSt").unwrap().len(), 2);
+
+        let output = encode_chunks_stream(input.as_slice(), Vec::new(), chunks(), Position::BeforeIend, OnExisting::Replace).unwrap();
+        let png = Png::try_from(output.as_ref()).unwrap();
+        assert_eq!(chunk_types(&png), vec!["IHDR", "sBIT", "IDAT", "RuSt", "IEND"]);
+        assert_eq!(decode(&png, "RuSt").unwrap(), Some("New secret".to_string()));
+
+        let output = encode_chunks_stream(input.as_slice(), Vec::new(), chunks(), Position::BeforeIend, OnExisting::Fail);
+        assert!(matches!(output, Err(PngSecretError::ChunkExists(_))));
+        let output = encode_chunks_stream(testing_png().as_bytes().as_slice(), Vec::new(), chunks(), Position::BeforeIend, OnExisting::Fail);
+        assert!(output.is_ok());
+    }
+
+    #[test]
+    fn test_encode_with_on_existing() {
+        let mut old = testing_png();
+        encode(&mut old, "RuSt", "Old secret").unwrap();
+        encode(&mut old, "okKo", "Other secret").unwrap();
+
+        let mut png = Png::try_from(old.as_bytes().as_slice()).unwrap();
+        encode_with(&mut png, "RuSt", "New secret", Position::BeforeIend, OnExisting::Append).unwrap();
+        assert_eq!(decode_all(&png, "RuSt").unwrap().len(), 2);
+        assert_eq!(decode(&png, "RuSt").unwrap(), Some("Old secret".to_string()));
+
+        let mut png = Png::try_from(old.as_bytes().as_slice()).unwrap();
+        encode_with(&mut png, "RuSt", "New secret", Position::BeforeIend, OnExisting::Replace).unwrap();
+        assert_eq!(chunk_types(&png), vec!["IHDR", "sBIT", "IDAT", "okKo", "RuSt", "IEND"]);
+        assert_eq!(decode(&png, "RuSt").unwrap(), Some("New secret".to_string()));
+
+        let mut png = Png::try_from(old.as_bytes().as_slice()).unwrap();
+        let result = encode_with(&mut png, "RuSt", "New secret", Position::BeforeIend, OnExisting::Fail);
+        assert!(matches!(result, Err(PngSecretError::ChunkExists(_))));
+        assert_eq!(png.as_bytes(), old.as_bytes());
+        let mut png = testing_png();
+        encode_with(&mut png, "RuSt", "New secret", Position::BeforeIend, OnExisting::Fail).unwrap();
+        assert_eq!(decode(&png, "RuSt").unwrap(), Some("New secret".to_string()));
+    }
+
+    #[test]
+    fn test_describe() {
+        let mut png = testing_png();
+        encode(&mut png, "ruSt", "Secret message").unwrap();
+        encode(&mut png, "biNa", Envelope::new(ContentType::Binary, vec![0xff, 0])).unwrap();
+        let attachment = Attachment::new("notes.txt", b"notes".to_vec()).unwrap();
+        encode(&mut png, "fiLe", &attachment).unwrap();
+        encode_split(&mut png, "paRt", "A secret too long for one chunk", 10, Position::BeforeIend).unwrap();
+        let alice = crate::recipient::Identity::generate();
+        encode(&mut png, "enCr", Envelope::from("Hidden").encrypt_to(&[alice.recipient()]).unwrap()).unwrap();
+
+        let infos: Vec<SecretInfo> = list(&png).into_iter().map(|chunk| describe(chunk).unwrap()).collect();
+        assert_eq!(infos[0], SecretInfo::Text("Secret message".to_string()));
+        assert_eq!(infos[1], SecretInfo::Binary(2));
+        assert_eq!(infos[2], SecretInfo::File(attachment));
+        assert!(matches!(infos[3], SecretInfo::Part(Part { index: 0, total: 4, .. })));
+        assert_eq!(infos[7], SecretInfo::Encrypted);
+    }
+
+    #[test]
+    fn test_rewrite_file() {
+        let directory = std::env::temp_dir().join(format!("png-secret-rewrite-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("image.png");
+        fs::write(&path, testing_png().as_bytes()).unwrap();
+
+        rewrite_file(&path, None, |input, output| {
+            encode_stream(input, output, "RuSt", "Secret message", Position::BeforeIend)
+        })
+        .unwrap();
+        let png = Png::from_path(&path).unwrap();
+        assert_eq!(decode(&png, "RuSt").unwrap(), Some("Secret message".to_string()));
+
+        // a failed rewrite leaves the file as it was, without the temporary file
+        let result = rewrite_file(&path, None, |input, output| remove_stream(input, output, "miSs"));
+        assert!(matches!(result, Err(PngSecretError::ChunkNotFound(_))));
+        assert_eq!(Png::from_path(&path).unwrap().as_bytes(), png.as_bytes());
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 1);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+            rewrite_file(&path, None, |input, output| remove_stream(input, output, "RuSt")).unwrap();
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o640);
+        }
+
+        // files left with the name of a temporary file are kept and don't
+        // block the rewrite
+        let stale_paths = [
+            path.with_extension("png-secret.tmp"),
+            path.with_extension(format!("png-secret.{}.0.tmp", std::process::id())),
+        ];
+        for stale_path in &stale_paths {
+            fs::write(stale_path, b"not mine").unwrap();
+        }
+        rewrite_file(&path, None, |input, output| {
+            encode_stream(input, output, "RuSt", "Secret message", Position::BeforeIend)
+        })
+        .unwrap();
+        assert_eq!(decode(&Png::from_path(&path).unwrap(), "RuSt").unwrap(), Some("Secret message".to_string()));
+        for stale_path in &stale_paths {
+            assert_eq!(fs::read(stale_path).unwrap(), b"not mine");
+        }
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 3);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_encode_before_idat() {
+        let mut png = testing_png();
+        encode_at(&mut png, "RuSt", "Secret message", Position::BeforeIdat).unwrap();
+        assert_eq!(chunk_types(&png), vec!["IHDR", "sBIT", "RuSt", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_encode_without_iend() {
+        let mut png = Png::from_chunks(vec![]);
+        encode_at(&mut png, "RuSt", "Secret message", Position::BeforeIdat).unwrap();
+        assert_eq!(chunk_types(&png), vec!["RuSt"]);
+    }
+
+    #[test]
+    fn test_decode_missing() {
+        let png = testing_png();
+        assert_eq!(decode(&png, "RuSt").unwrap(), None);
+    }
+
+    #[test]
+    fn test_encode_invalid_chunk_type() {
+        let mut png = testing_png();
+        assert!(encode(&mut png, "Ru5t", "Secret message").is_err());
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut png = testing_png();
+        encode(&mut png, "RuSt", "Secret message").unwrap();
+        let chunk = remove(&mut png, "RuSt").unwrap();
+        assert_eq!(Envelope::read(chunk.data()).unwrap().text().unwrap(), "Secret message");
+        assert_eq!(decode(&png, "RuSt").unwrap(), None);
+    }
+
+    #[test]
+    fn test_encode_stream() {
+        let input = testing_png().as_bytes();
+        let output = encode_stream(input.as_slice(), Vec::new(), "RuSt", "Secret message", Position::BeforeIend).unwrap();
+
+        let png = Png::try_from(output.as_ref()).unwrap();
+        assert_eq!(chunk_types(&png), vec!["IHDR", "sBIT", "IDAT", "RuSt", "IEND"]);
+        assert_eq!(decode(&png, "RuSt").unwrap(), Some("Secret message".to_string()));
+
+        let output = encode_stream(input.as_slice(), Vec::new(), "RuSt", "Secret message", Position::BeforeIdat).unwrap();
+
+        let png = Png::try_from(output.as_ref()).unwrap();
+        assert_eq!(chunk_types(&png), vec!["IHDR", "sBIT", "RuSt", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_encode_stream_after_iend() {
+        // secrets written by older versions are after IEND
+        let mut png = testing_png();
+        png.append_chunk(Chunk::new(ChunkType::from_str("olDr").unwrap(), b"Old message".to_vec()));
+        let input = png.as_bytes();
+
+        let output = encode_stream(input.as_slice(), Vec::new(), "RuSt", "Secret message", Position::BeforeIend).unwrap();
+
+        let png = Png::try_from(output.as_ref()).unwrap();
+        assert_eq!(chunk_types(&png), vec!["IHDR", "sBIT", "IDAT", "olDr", "RuSt", "IEND"]);
+    }
+
+    #[test]
+    fn test_remove_stream() {
+        let mut png = testing_png();
+        encode(&mut png, "RuSt", "Secret message").unwrap();
+        let input = png.as_bytes();
+
+        let (chunk, output) = remove_stream(input.as_slice(), Vec::new(), "RuSt").unwrap();
+        assert_eq!(Envelope::read(chunk.data()).unwrap().text().unwrap(), "Secret message");
+        assert_eq!(output, testing_png().as_bytes());
+    }
+
+    #[test]
+    fn test_remove_stream_missing() {
+        let input = testing_png().as_bytes();
+        let result = remove_stream(input.as_slice(), Vec::new(), "RuSt");
+        assert!(matches!(result, Err(PngSecretError::ChunkNotFound(_))));
+    }
+
+    #[test]
+    fn test_encode_decode_text() {
+        let mut png = testing_png();
+        encode_text(&mut png, "tEXt", "Comment", "Secret message", Position::BeforeIend).unwrap();
+        encode_text(&mut png, "iTXt", "Comment", "秘密", Position::BeforeIend).unwrap();
+        encode_text(&mut png, "zTXt", "Comment", "Compressed", Position::BeforeIend).unwrap();
+        assert_eq!(chunk_types(&png), vec!["IHDR", "sBIT", "IDAT", "tEXt", "iTXt", "zTXt", "IEND"]);
+
+        assert_eq!(decode_text(&png, "tEXt", "Comment").unwrap(), Some("Secret message".to_string()));
+        assert_eq!(decode_text(&png, "iTXt", "Comment").unwrap(), Some("秘密".to_string()));
+        assert_eq!(decode_text(&png, "zTXt", "Comment").unwrap(), Some("Compressed".to_string()));
+        assert_eq!(decode_text(&png, "tEXt", "Title").unwrap(), None);
+    }
+
+    #[test]
+    fn test_text_chunk() {
+        assert!(text_chunk("tEXt", "Comment", "秘密").is_err());
+        assert!(text_chunk("RuSt", "Comment", "Secret message").is_err());
+        assert_eq!(text_chunk("iTXt", "Comment", "秘密").unwrap().chunk_type().to_string(), "iTXt");
+    }
+
+    #[test]
+    fn test_list_text() {
+        let mut png = testing_png();
+        encode_text(&mut png, "tEXt", "Comment", "Secret message", Position::BeforeIend).unwrap();
+        encode(&mut png, "RuSt", "Not a text").unwrap();
+
+        let texts = list_text(&png);
+        assert_eq!(texts.len(), 1);
+        assert_eq!(texts[0].keyword(), "Comment");
+        assert_eq!(list(&png).len(), 1);
+    }
+
+    #[test]
+    fn test_encode_decode_lsb() {
+        let mut png = image_png();
+        let options = LsbOptions::default();
+        encode_lsb(&mut png, "RuSt", "Secret message", &options).unwrap();
+
+        let png = Png::try_from(png.as_bytes().as_ref()).unwrap();
+        assert_eq!(chunk_types(&png), vec!["IHDR", "sBIT", "IDAT", "IEND"]);
+        assert_eq!(decode_lsb(&png, "RuSt", &options).unwrap(), Some("Secret message".to_string()));
+        assert_eq!(decode_lsb(&png, "TeSt", &options).unwrap(), None);
+        assert_eq!(decode_lsb_envelope(&png, "RuSt", &options).unwrap(), Some(Envelope::from("Secret message")));
+        assert_eq!(decode(&png, "RuSt").unwrap(), None);
+    }
+
+    #[test]
+    fn test_list() {
+        let mut png = testing_png();
+        png.insert_before("IDAT", Chunk::new(ChunkType::from_str("gAMA").unwrap(), b"abcd".to_vec())).unwrap();
+        assert!(list(&png).is_empty());
+
+        encode(&mut png, "RuSt", "First").unwrap();
+        encode(&mut png, "TeSt", "Second").unwrap();
+        png.insert_before("IEND", Chunk::new(ChunkType::from_str("biNa").unwrap(), vec![0xff, 0xfe])).unwrap();
+        png.insert_before("IEND", Chunk::new(ChunkType::from_str("enCr").unwrap(), b"PSENC\x01\x00\xff".to_vec())).unwrap();
+        let secrets: Vec<String> = list(&png).iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(secrets, vec!["RuSt", "TeSt", "enCr"]);
+    }
+}
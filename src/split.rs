@@ -0,0 +1,227 @@
+//! Secrets spread over several chunks of the same type, to keep each chunk
+//! small.
+//!
+//! Every part is an envelope with the `split` flag, the content type and
+//! other flags of the whole secret. Its payload starts with :
+//!
+//! | bytes | content                                        |
+//! |-------|------------------------------------------------|
+//! | 4     | payload id, random, the same in every part     |
+//! | 2     | index of the part from 0, u16 BE               |
+//! | 2     | number of parts, u16 BE                        |
+//! | ..    | the bytes of the secret payload for this part  |
+
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+
+use crate::envelope::{Envelope, Flags};
+use crate::{PngSecretError, Result};
+
+const PART_HEADER_LENGTH: usize = 8;
+
+/// Where a part goes in its secret.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Part {
+    pub payload_id: u32,
+    pub index: u16,
+    pub total: u16,
+}
+
+impl Part {
+    /// The part header of a split envelope, `None` if it isn't split.
+    pub fn of(envelope: &Envelope) -> Option<Part> {
+        if !envelope.flags.split || envelope.payload.len() < PART_HEADER_LENGTH {
+            return None;
+        }
+        let payload = &envelope.payload;
+        Some(Part {
+            payload_id: u32::from_be_bytes(payload[0..4].try_into().unwrap()),
+            index: u16::from_be_bytes(payload[4..6].try_into().unwrap()),
+            total: u16::from_be_bytes(payload[6..8].try_into().unwrap()),
+        })
+    }
+}
+
+/// A secret put back together from its parts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reassembled {
+    pub envelope: Envelope,
+    /// Number of parts the secret was read from, 1 if it wasn't split.
+    pub parts: u16,
+    /// The parts weren't stored in order.
+    pub reordered: bool,
+}
+
+/// Split `envelope` in parts holding at most `part_size` bytes of its
+/// payload. A payload fitting in one part is returned as is.
+pub fn split(envelope: &Envelope, part_size: usize) -> Result<Vec<Envelope>> {
+    if part_size == 0 {
+        return Err(PngSecretError::InvalidEnvelope("the part size can't be 0".to_string()));
+    }
+    if envelope.payload.len() <= part_size {
+        return Ok(vec![envelope.clone()]);
+    }
+
+    let total = envelope.payload.len().div_ceil(part_size);
+    let total = u16::try_from(total)
+        .map_err(|_| PngSecretError::InvalidEnvelope(format!("{} parts is too many, the limit is {}", total, u16::MAX)))?;
+    let payload_id = OsRng.next_u32();
+
+    let parts = envelope.payload.chunks(part_size).enumerate().map(|(index, data)| {
+        let mut payload = Vec::with_capacity(PART_HEADER_LENGTH + data.len());
+        payload.extend_from_slice(&payload_id.to_be_bytes());
+        payload.extend_from_slice(&(index as u16).to_be_bytes());
+        payload.extend_from_slice(&total.to_be_bytes());
+        payload.extend_from_slice(data);
+
+        Envelope {
+            flags: Flags { split: true, ..envelope.flags },
+            content_type: envelope.content_type,
+            payload,
+        }
+    });
+    Ok(parts.collect())
+}
+
+/// Put back together the secret of the first envelope of `envelopes`.
+///
+/// The other parts of the same secret are looked for in the rest of
+/// `envelopes`, in any order. Fails with [`PngSecretError::MissingParts`] if
+/// some can't be found.
+pub fn join(envelopes: &[Envelope]) -> Result<Reassembled> {
+    let first = envelopes
+        .first()
+        .ok_or_else(|| PngSecretError::InvalidEnvelope("no part to join".to_string()))?;
+
+    let Some(first_part) = Part::of(first) else {
+        if first.flags.split {
+            return Err(PngSecretError::InvalidEnvelope("truncated part header".to_string()));
+        }
+        return Ok(Reassembled {
+            envelope: first.clone(),
+            parts: 1,
+            reordered: false,
+        });
+    };
+
+    let mut parts: Vec<Option<&[u8]>> = vec![None; first_part.total as usize];
+    let mut previous_index = None;
+    let mut reordered = false;
+
+    for envelope in envelopes {
+        let part = match Part::of(envelope) {
+            Some(part) if part.payload_id == first_part.payload_id => part,
+            _ => continue,
+        };
+        if part.total != first_part.total || part.index >= part.total {
+            return Err(PngSecretError::InvalidEnvelope(format!(
+                "part {} of {} doesn't match the {} parts of the secret",
+                part.index, part.total, first_part.total
+            )));
+        }
+        if parts[part.index as usize].is_some() {
+            return Err(PngSecretError::InvalidEnvelope(format!("part {} is stored twice", part.index)));
+        }
+
+        reordered |= previous_index.is_some_and(|previous| part.index < previous);
+        previous_index = Some(part.index);
+        parts[part.index as usize] = Some(&envelope.payload[PART_HEADER_LENGTH..]);
+    }
+
+    let missing: Vec<u16> = (0..first_part.total).filter(|index| parts[*index as usize].is_none()).collect();
+    if !missing.is_empty() {
+        return Err(PngSecretError::MissingParts {
+            missing,
+            total: first_part.total,
+        });
+    }
+
+    Ok(Reassembled {
+        envelope: Envelope {
+            flags: Flags { split: false, ..first.flags },
+            content_type: first.content_type,
+            payload: parts.into_iter().flatten().flatten().copied().collect(),
+        },
+        parts: first_part.total,
+        reordered,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::envelope::ContentType;
+
+    fn message() -> Envelope {
+        Envelope::from("A secret too long for one chunk")
+    }
+
+    #[test]
+    fn test_split_join() {
+        let parts = split(&message(), 10).unwrap();
+        assert_eq!(parts.len(), 4);
+        assert!(parts.iter().all(|part| part.flags.split && part.content_type == ContentType::Text));
+
+        let headers: Vec<Part> = parts.iter().map(|part| Part::of(part).unwrap()).collect();
+        assert!(headers.iter().all(|header| header.payload_id == headers[0].payload_id && header.total == 4));
+        assert_eq!(headers.iter().map(|header| header.index).collect::<Vec<_>>(), [0, 1, 2, 3]);
+
+        let reassembled = join(&parts).unwrap();
+        assert_eq!(reassembled.envelope, message());
+        assert_eq!(reassembled.parts, 4);
+        assert!(!reassembled.reordered);
+    }
+
+    #[test]
+    fn test_not_split() {
+        let parts = split(&message(), 100).unwrap();
+        assert_eq!(parts, vec![message()]);
+        assert_eq!(Part::of(&parts[0]), None);
+
+        let reassembled = join(&parts).unwrap();
+        assert_eq!(reassembled.envelope, message());
+        assert_eq!(reassembled.parts, 1);
+
+        assert!(split(&message(), 0).is_err());
+        assert!(join(&[]).is_err());
+    }
+
+    #[test]
+    fn test_reordered() {
+        let mut parts = split(&message(), 10).unwrap();
+        parts.swap(1, 3);
+
+        let reassembled = join(&parts).unwrap();
+        assert_eq!(reassembled.envelope, message());
+        assert!(reassembled.reordered);
+    }
+
+    #[test]
+    fn test_other_secret() {
+        // the parts of another secret with the same chunk type are skipped
+        let mut parts = split(&message(), 10).unwrap();
+        let other = split(&Envelope::from("Another secret, also split"), 10).unwrap();
+        parts.insert(2, other[0].clone());
+        parts.push(other[1].clone());
+
+        assert_eq!(join(&parts).unwrap().envelope, message());
+    }
+
+    #[test]
+    fn test_missing() {
+        let mut parts = split(&message(), 10).unwrap();
+        parts.remove(2);
+        parts.remove(0);
+
+        // the first part found decides the secret, here part 1
+        let error = join(&parts).unwrap_err();
+        assert!(matches!(error, PngSecretError::MissingParts { ref missing, total: 4 } if missing == &[0, 2]));
+    }
+
+    #[test]
+    fn test_duplicate() {
+        let mut parts = split(&message(), 10).unwrap();
+        parts.push(parts[1].clone());
+        assert!(matches!(join(&parts), Err(PngSecretError::InvalidEnvelope(_))));
+    }
+}
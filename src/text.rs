@@ -0,0 +1,391 @@
+//! Standard text chunks : `tEXt`, `zTXt` and `iTXt`.
+//!
+//! Every text chunk has a keyword, like `Title` or `Comment`, telling what the
+//! text is about.
+
+use std::str::FromStr;
+
+use crate::ancillary::{latin1_to_string, read_keyword, string_to_latin1, write_keyword};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::zlib;
+use crate::{PngSecretError, Result};
+
+/// `tEXt` : keyword and text, both Latin-1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Text {
+    pub keyword: String,
+    pub text: String,
+}
+
+impl Text {
+    pub fn new(keyword: &str, text: &str) -> Result<Text> {
+        check_keyword("tEXt", keyword)?;
+        if !is_latin1(text) {
+            return Err(invalid("tEXt", "text must be Latin-1, use iTXt".to_string()));
+        }
+
+        Ok(Text {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+        })
+    }
+}
+
+impl TryFrom<&Chunk> for Text {
+    type Error = PngSecretError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let (keyword, text) = read_keyword("tEXt", check_type(chunk, "tEXt")?)?;
+        Ok(Text {
+            keyword,
+            text: latin1_to_string(text),
+        })
+    }
+}
+
+impl From<Text> for Chunk {
+    fn from(value: Text) -> Self {
+        let mut data = write_keyword(&value.keyword);
+        data.extend(string_to_latin1(&value.text));
+        new_chunk("tEXt", data)
+    }
+}
+
+/// `zTXt` : keyword and zlib compressed Latin-1 text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZText {
+    pub keyword: String,
+    pub compressed_text: Vec<u8>,
+}
+
+impl ZText {
+    pub fn new(keyword: &str, text: &str) -> Result<ZText> {
+        check_keyword("zTXt", keyword)?;
+        if !is_latin1(text) {
+            return Err(invalid("zTXt", "text must be Latin-1, use iTXt".to_string()));
+        }
+
+        Ok(ZText {
+            keyword: keyword.to_string(),
+            compressed_text: zlib::compress(&string_to_latin1(text), 9),
+        })
+    }
+
+    /// Decompress the text.
+    pub fn text(&self) -> Result<String> {
+        let text = zlib::decompress(&self.compressed_text, zlib::DEFAULT_LIMIT)?;
+        Ok(latin1_to_string(&text))
+    }
+}
+
+impl TryFrom<&Chunk> for ZText {
+    type Error = PngSecretError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let (keyword, rest) = read_keyword("zTXt", check_type(chunk, "zTXt")?)?;
+        match rest.split_first() {
+            Some((0, compressed_text)) => Ok(ZText {
+                keyword,
+                compressed_text: compressed_text.to_vec(),
+            }),
+            Some((method, _)) => Err(invalid("zTXt", format!("unknown compression method {}", method))),
+            None => Err(invalid("zTXt", "missing compression method".to_string())),
+        }
+    }
+}
+
+impl From<ZText> for Chunk {
+    fn from(value: ZText) -> Self {
+        let mut data = write_keyword(&value.keyword);
+        data.push(0);
+        data.extend(value.compressed_text);
+        new_chunk("zTXt", data)
+    }
+}
+
+/// `iTXt` : international text, in UTF-8, with the language of the text and
+/// the keyword translated in this language.
+///
+/// `text` is always uncompressed, `compressed` tells how it is stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IText {
+    pub keyword: String,
+    pub compressed: bool,
+    pub language_tag: String,
+    pub translated_keyword: String,
+    pub text: String,
+}
+
+impl IText {
+    pub fn new(keyword: &str, text: &str) -> Result<IText> {
+        check_keyword("iTXt", keyword)?;
+
+        Ok(IText {
+            keyword: keyword.to_string(),
+            compressed: false,
+            language_tag: String::new(),
+            translated_keyword: String::new(),
+            text: text.to_string(),
+        })
+    }
+}
+
+impl TryFrom<&Chunk> for IText {
+    type Error = PngSecretError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let (keyword, rest) = read_keyword("iTXt", check_type(chunk, "iTXt")?)?;
+
+        let (compression_flag, compression_method, rest) = match rest {
+            [flag, method, rest @ ..] => (*flag, *method, rest),
+            _ => return Err(invalid("iTXt", "missing compression flag".to_string())),
+        };
+        if compression_flag > 1 {
+            return Err(invalid("iTXt", format!("invalid compression flag {}", compression_flag)));
+        }
+        if compression_method != 0 {
+            return Err(invalid("iTXt", format!("unknown compression method {}", compression_method)));
+        }
+
+        let (language_tag, rest) = split_null("iTXt", rest)?;
+        let (translated_keyword, text) = split_null("iTXt", rest)?;
+        let compressed = compression_flag == 1;
+        let text = match compressed {
+            true => zlib::decompress(text, zlib::DEFAULT_LIMIT)?,
+            false => text.to_vec(),
+        };
+
+        Ok(IText {
+            keyword,
+            compressed,
+            language_tag: latin1_to_string(language_tag),
+            translated_keyword: utf8("iTXt", translated_keyword)?,
+            text: utf8("iTXt", &text)?,
+        })
+    }
+}
+
+impl From<IText> for Chunk {
+    fn from(value: IText) -> Self {
+        let mut data = write_keyword(&value.keyword);
+        data.extend([value.compressed as u8, 0]);
+        data.extend(string_to_latin1(&value.language_tag));
+        data.push(0);
+        data.extend(value.translated_keyword.as_bytes());
+        data.push(0);
+        match value.compressed {
+            true => data.extend(zlib::compress(value.text.as_bytes(), 9)),
+            false => data.extend(value.text.as_bytes()),
+        }
+        new_chunk("iTXt", data)
+    }
+}
+
+/// Any of the three text chunks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextChunk {
+    Text(Text),
+    ZText(ZText),
+    IText(IText),
+}
+
+impl TextChunk {
+    pub fn keyword(&self) -> &str {
+        match self {
+            TextChunk::Text(text) => &text.keyword,
+            TextChunk::ZText(text) => &text.keyword,
+            TextChunk::IText(text) => &text.keyword,
+        }
+    }
+
+    /// The text, decompressed if needed.
+    pub fn text(&self) -> Result<String> {
+        match self {
+            TextChunk::Text(text) => Ok(text.text.clone()),
+            TextChunk::ZText(text) => text.text(),
+            TextChunk::IText(text) => Ok(text.text.clone()),
+        }
+    }
+
+    /// `true` if `chunk_type` is one of the text chunk types.
+    pub fn is_text_chunk(chunk_type: &ChunkType) -> bool {
+        matches!(&chunk_type.bytes(), b"tEXt" | b"zTXt" | b"iTXt")
+    }
+}
+
+impl TryFrom<&Chunk> for TextChunk {
+    type Error = PngSecretError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        match &chunk.chunk_type().bytes() {
+            b"tEXt" => Ok(TextChunk::Text(Text::try_from(chunk)?)),
+            b"zTXt" => Ok(TextChunk::ZText(ZText::try_from(chunk)?)),
+            b"iTXt" => Ok(TextChunk::IText(IText::try_from(chunk)?)),
+            _ => Err(PngSecretError::InvalidChunkData {
+                chunk_type: chunk.chunk_type().to_string(),
+                reason: "not a text chunk".to_string(),
+            }),
+        }
+    }
+}
+
+impl From<TextChunk> for Chunk {
+    fn from(value: TextChunk) -> Self {
+        match value {
+            TextChunk::Text(text) => text.into(),
+            TextChunk::ZText(text) => text.into(),
+            TextChunk::IText(text) => text.into(),
+        }
+    }
+}
+
+fn check_type<'a>(chunk: &'a Chunk, chunk_type: &str) -> Result<&'a [u8]> {
+    if chunk.chunk_type().to_string() != chunk_type {
+        return Err(invalid(chunk_type, format!("expected {}, found {}", chunk_type, chunk.chunk_type())));
+    }
+    Ok(chunk.data())
+}
+
+fn check_keyword(chunk_type: &str, keyword: &str) -> Result<()> {
+    let length = keyword.chars().count();
+    if length == 0 || length > 79 || !is_latin1(keyword) || keyword.contains('\0') {
+        return Err(invalid(chunk_type, format!("invalid keyword '{}'", keyword)));
+    }
+    Ok(())
+}
+
+fn is_latin1(text: &str) -> bool {
+    text.chars().all(|c| (c as u32) < 256)
+}
+
+fn split_null<'a>(chunk_type: &str, data: &'a [u8]) -> Result<(&'a [u8], &'a [u8])> {
+    let end = data
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or_else(|| invalid(chunk_type, "missing null separator".to_string()))?;
+    Ok((&data[..end], &data[end + 1..]))
+}
+
+fn utf8(chunk_type: &str, bytes: &[u8]) -> Result<String> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| invalid(chunk_type, "text isn't valid UTF-8".to_string()))
+}
+
+fn invalid(chunk_type: &str, reason: String) -> PngSecretError {
+    PngSecretError::InvalidChunkData {
+        chunk_type: chunk_type.to_string(),
+        reason,
+    }
+}
+
+fn new_chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+    Chunk::new(ChunkType::from_str(chunk_type).expect("valid chunk type"), data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_text() {
+        let text = Text::try_from(&chunk("tEXt", b"Comment\0Caf\xe9")).unwrap();
+        assert_eq!(text.keyword, "Comment");
+        assert_eq!(text.text, "Café");
+        assert_eq!(Chunk::from(text), chunk("tEXt", b"Comment\0Caf\xe9"));
+
+        assert!(Text::try_from(&chunk("tEXt", b"Comment")).is_err());
+        assert!(Text::try_from(&chunk("zTXt", b"Comment\0Text")).is_err());
+    }
+
+    #[test]
+    fn test_new_text() {
+        let text = Text::new("Comment", "Café").unwrap();
+        assert_eq!(Text::try_from(&Chunk::from(text.clone())).unwrap(), text);
+
+        assert!(Text::new("Comment", "日本").is_err());
+        assert!(Text::new("", "Text").is_err());
+        assert!(Text::new(&"a".repeat(80), "Text").is_err());
+    }
+
+    #[test]
+    fn test_ztext() {
+        let ztext = ZText::try_from(&chunk("zTXt", b"Comment\0\0\x78\x9c")).unwrap();
+        assert_eq!(ztext.keyword, "Comment");
+        assert_eq!(ztext.compressed_text, vec![0x78, 0x9c]);
+        assert_eq!(Chunk::from(ztext), chunk("zTXt", b"Comment\0\0\x78\x9c"));
+
+        assert!(ZText::try_from(&chunk("zTXt", b"Comment\0\x01\x78\x9c")).is_err());
+        assert!(ZText::try_from(&chunk("zTXt", b"Comment\0\0\x78\x9c")).unwrap().text().is_err());
+        assert!(ZText::try_from(&chunk("zTXt", b"Comment\0")).is_err());
+    }
+
+    #[test]
+    fn test_new_ztext() {
+        let ztext = ZText::new("Comment", &"Café ".repeat(100)).unwrap();
+        assert!(ztext.compressed_text.len() < 100);
+        assert_eq!(ztext.text().unwrap(), "Café ".repeat(100));
+
+        let chunk = Chunk::from(ztext.clone());
+        assert_eq!(ZText::try_from(&chunk).unwrap(), ztext);
+        assert_eq!(TextChunk::try_from(&chunk).unwrap().text().unwrap(), "Café ".repeat(100));
+
+        assert!(ZText::new("Comment", "日本").is_err());
+    }
+
+    #[test]
+    fn test_compressed_itext() {
+        let mut itext = IText::new("Comment", &"日本".repeat(100)).unwrap();
+        itext.compressed = true;
+
+        let chunk = Chunk::from(itext.clone());
+        assert!(chunk.length() < 100);
+        assert_eq!(IText::try_from(&chunk).unwrap(), itext);
+    }
+
+    #[test]
+    fn test_itext() {
+        let data = "Title\0\0\0fr\0Titre\0Le chat élégant".as_bytes();
+        let itext = IText::try_from(&chunk("iTXt", data)).unwrap();
+        assert_eq!(itext.keyword, "Title");
+        assert!(!itext.compressed);
+        assert_eq!(itext.language_tag, "fr");
+        assert_eq!(itext.translated_keyword, "Titre");
+        assert_eq!(itext.text, "Le chat élégant");
+        assert_eq!(Chunk::from(itext), chunk("iTXt", data));
+    }
+
+    #[test]
+    fn test_new_itext() {
+        let itext = IText::new("Comment", "日本").unwrap();
+        assert_eq!(IText::try_from(&Chunk::from(itext.clone())).unwrap(), itext);
+    }
+
+    #[test]
+    fn test_invalid_itext() {
+        assert!(IText::try_from(&chunk("iTXt", b"Title\0")).is_err());
+        assert!(IText::try_from(&chunk("iTXt", b"Title\0\0\0fr")).is_err());
+        assert!(IText::try_from(&chunk("iTXt", b"Title\0\0\x01\0\0text")).is_err());
+        assert!(IText::try_from(&chunk("iTXt", b"Title\0\x02\0\0\0text")).is_err());
+        assert!(IText::try_from(&chunk("iTXt", b"Title\0\x01\0\0\0text")).is_err());
+        assert!(IText::try_from(&chunk("iTXt", b"Title\0\0\0\0\0\xff")).is_err());
+    }
+
+    #[test]
+    fn test_text_chunk() {
+        let text = TextChunk::try_from(&chunk("tEXt", b"Comment\0Text")).unwrap();
+        assert_eq!(text.keyword(), "Comment");
+        assert_eq!(text.text().unwrap(), "Text");
+
+        let text = TextChunk::try_from(&chunk("iTXt", b"Title\0\0\0\0\0Text")).unwrap();
+        assert_eq!(text.keyword(), "Title");
+        assert_eq!(text.text().unwrap(), "Text");
+
+        assert!(TextChunk::try_from(&chunk("RuSt", b"Comment\0Text")).is_err());
+        assert!(TextChunk::is_text_chunk(&ChunkType::from_str("zTXt").unwrap()));
+        assert!(!TextChunk::is_text_chunk(&ChunkType::from_str("RuSt").unwrap()));
+    }
+}
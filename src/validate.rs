@@ -0,0 +1,323 @@
+use std::fmt::Display;
+
+/// How bad a [`Violation`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Decoders usually still read the image.
+    Warning,
+    /// The file breaks the PNG spec, decoders can reject it.
+    Error,
+}
+
+/// A chunk ordering rule of the PNG spec broken by a png.
+///
+/// `index` is the position of the faulty chunk in the chunk list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// The png has no `IHDR` chunk.
+    MissingIhdr,
+    /// `IHDR` isn't the first chunk.
+    IhdrNotFirst { index: usize },
+    /// The png has no `IDAT` chunk.
+    MissingIdat,
+    /// `IDAT` chunks are separated by other chunks.
+    IdatNotConsecutive { index: usize },
+    /// The png has no `IEND` chunk.
+    MissingIend,
+    /// Some chunks come after `IEND`.
+    IendNotLast { index: usize },
+    /// A chunk allowed once appears again.
+    Duplicate { chunk_type: String, index: usize },
+    /// A chunk comes after `before`, it must come before it.
+    MisplacedBefore { chunk_type: String, before: String, index: usize },
+    /// A chunk comes before `after`, it must come after it.
+    MisplacedAfter { chunk_type: String, after: String, index: usize },
+    /// `iCCP` and `sRGB` must not both be present.
+    IccpWithSrgb { index: usize },
+    /// A critical chunk unknown to decoders, they must reject the image.
+    UnknownCritical { chunk_type: String, index: usize },
+    /// The reserved bit of the chunk type is set.
+    ReservedBit { chunk_type: String, index: usize },
+}
+
+impl Violation {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Violation::MisplacedBefore { chunk_type, .. }
+            | Violation::MisplacedAfter { chunk_type, .. }
+            | Violation::Duplicate { chunk_type, .. }
+                if !is_critical(chunk_type) =>
+            {
+                Severity::Warning
+            }
+            Violation::IccpWithSrgb { .. } | Violation::ReservedBit { .. } => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::MissingIhdr => write!(f, "IHDR chunk is missing"),
+            Violation::IhdrNotFirst { index } => write!(f, "IHDR is chunk {} instead of the first", index),
+            Violation::MissingIdat => write!(f, "IDAT chunk is missing"),
+            Violation::IdatNotConsecutive { index } => {
+                write!(f, "IDAT chunk {} doesn't follow the previous IDAT", index)
+            }
+            Violation::MissingIend => write!(f, "IEND chunk is missing"),
+            Violation::IendNotLast { index } => write!(f, "chunk {} comes after IEND", index),
+            Violation::Duplicate { chunk_type, index } => {
+                write!(f, "{} chunk {} appears more than once", chunk_type, index)
+            }
+            Violation::MisplacedBefore { chunk_type, before, index } => {
+                write!(f, "{} chunk {} must come before {}", chunk_type, index, before)
+            }
+            Violation::MisplacedAfter { chunk_type, after, index } => {
+                write!(f, "{} chunk {} must come after {}", chunk_type, index, after)
+            }
+            Violation::IccpWithSrgb { index } => {
+                write!(f, "chunk {} : iCCP and sRGB must not both be present", index)
+            }
+            Violation::UnknownCritical { chunk_type, index } => {
+                write!(f, "{} chunk {} is critical but unknown", chunk_type, index)
+            }
+            Violation::ReservedBit { chunk_type, index } => {
+                write!(f, "{} chunk {} has its reserved bit set", chunk_type, index)
+            }
+        }
+    }
+}
+
+const CRITICAL_CHUNKS: [&str; 4] = ["IHDR", "PLTE", "IDAT", "IEND"];
+
+// chunks allowed only once in a png
+const SINGLE_CHUNKS: [&str; 13] = [
+    "IHDR", "PLTE", "IEND", "cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "bKGD", "hIST", "tRNS", "pHYs",
+    "tIME",
+];
+
+// (chunk, chunk it must come before)
+const BEFORE: [(&str, &str); 15] = [
+    ("PLTE", "IDAT"),
+    ("cHRM", "PLTE"),
+    ("cHRM", "IDAT"),
+    ("gAMA", "PLTE"),
+    ("gAMA", "IDAT"),
+    ("iCCP", "PLTE"),
+    ("iCCP", "IDAT"),
+    ("sBIT", "PLTE"),
+    ("sBIT", "IDAT"),
+    ("sRGB", "PLTE"),
+    ("sRGB", "IDAT"),
+    ("bKGD", "IDAT"),
+    ("hIST", "IDAT"),
+    ("tRNS", "IDAT"),
+    ("pHYs", "IDAT"),
+];
+
+// (chunk, chunk it must come after)
+const AFTER: [(&str, &str); 3] = [("bKGD", "PLTE"), ("hIST", "PLTE"), ("tRNS", "PLTE")];
+
+fn is_critical(chunk_type: &str) -> bool {
+    chunk_type.as_bytes().first().is_some_and(u8::is_ascii_uppercase)
+}
+
+/// Check `chunk_types`, in file order, against the chunk ordering rules.
+pub(crate) fn validate(chunk_types: &[String]) -> Vec<Violation> {
+    let mut violations = Vec::new();
+
+    let first_index = |chunk_type: &str| chunk_types.iter().position(|c| c == chunk_type);
+    let last_index = |chunk_type: &str| chunk_types.iter().rposition(|c| c == chunk_type);
+
+    // IHDR first
+    match first_index("IHDR") {
+        None => violations.push(Violation::MissingIhdr),
+        Some(0) => {}
+        Some(index) => violations.push(Violation::IhdrNotFirst { index }),
+    }
+
+    // IDAT consecutive
+    match first_index("IDAT") {
+        None => violations.push(Violation::MissingIdat),
+        Some(first) => {
+            for (index, chunk_type) in chunk_types.iter().enumerate().skip(first + 1) {
+                if chunk_type == "IDAT" && chunk_types[index - 1] != "IDAT" {
+                    violations.push(Violation::IdatNotConsecutive { index });
+                }
+            }
+        }
+    }
+
+    // IEND last
+    match first_index("IEND") {
+        None => violations.push(Violation::MissingIend),
+        Some(iend) => {
+            for (index, chunk_type) in chunk_types.iter().enumerate().skip(iend + 1) {
+                if chunk_type != "IEND" {
+                    violations.push(Violation::IendNotLast { index });
+                }
+            }
+        }
+    }
+
+    for (index, chunk_type) in chunk_types.iter().enumerate() {
+        if SINGLE_CHUNKS.contains(&chunk_type.as_str()) && first_index(chunk_type) != Some(index) {
+            violations.push(Violation::Duplicate {
+                chunk_type: chunk_type.clone(),
+                index,
+            });
+        }
+
+        for (chunk, before) in BEFORE {
+            if chunk == chunk_type && first_index(before).is_some_and(|b| b < index) {
+                violations.push(Violation::MisplacedBefore {
+                    chunk_type: chunk_type.clone(),
+                    before: before.to_string(),
+                    index,
+                });
+            }
+        }
+
+        for (chunk, after) in AFTER {
+            if chunk == chunk_type && last_index(after).is_some_and(|a| a > index) {
+                violations.push(Violation::MisplacedAfter {
+                    chunk_type: chunk_type.clone(),
+                    after: after.to_string(),
+                    index,
+                });
+            }
+        }
+
+        if chunk_type == "sRGB" || chunk_type == "iCCP" {
+            let other = if chunk_type == "sRGB" { "iCCP" } else { "sRGB" };
+            if first_index(other).is_some_and(|o| o < index) {
+                violations.push(Violation::IccpWithSrgb { index });
+            }
+        }
+
+        if is_critical(chunk_type) && !CRITICAL_CHUNKS.contains(&chunk_type.as_str()) {
+            violations.push(Violation::UnknownCritical {
+                chunk_type: chunk_type.clone(),
+                index,
+            });
+        }
+
+        if chunk_type.as_bytes().get(2).is_some_and(u8::is_ascii_lowercase) {
+            violations.push(Violation::ReservedBit {
+                chunk_type: chunk_type.clone(),
+                index,
+            });
+        }
+    }
+
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(chunk_types: &[&str]) -> Vec<Violation> {
+        let chunk_types: Vec<String> = chunk_types.iter().map(|c| c.to_string()).collect();
+        validate(&chunk_types)
+    }
+
+    #[test]
+    fn test_valid_png() {
+        assert!(check(&["IHDR", "gAMA", "PLTE", "tRNS", "IDAT", "IDAT", "tEXt", "IEND"]).is_empty());
+    }
+
+    #[test]
+    fn test_empty_png() {
+        assert_eq!(
+            check(&[]),
+            vec![Violation::MissingIhdr, Violation::MissingIdat, Violation::MissingIend]
+        );
+    }
+
+    #[test]
+    fn test_ihdr_not_first() {
+        assert_eq!(check(&["tEXt", "IHDR", "IDAT", "IEND"]), vec![Violation::IhdrNotFirst { index: 1 }]);
+    }
+
+    #[test]
+    fn test_iend_not_last() {
+        let violations = check(&["IHDR", "IDAT", "IEND", "ruSt"]);
+        assert_eq!(violations, vec![Violation::IendNotLast { index: 3 }]);
+        assert_eq!(violations[0].severity(), Severity::Error);
+    }
+
+    #[test]
+    fn test_duplicate_iend() {
+        let violations = check(&["IHDR", "IDAT", "IEND", "IEND"]);
+        assert_eq!(
+            violations,
+            vec![Violation::Duplicate { chunk_type: "IEND".to_string(), index: 3 }]
+        );
+    }
+
+    #[test]
+    fn test_idat_not_consecutive() {
+        let violations = check(&["IHDR", "IDAT", "tEXt", "IDAT", "IEND"]);
+        assert_eq!(violations, vec![Violation::IdatNotConsecutive { index: 3 }]);
+    }
+
+    #[test]
+    fn test_plte_after_idat() {
+        let violations = check(&["IHDR", "IDAT", "PLTE", "IEND"]);
+        assert_eq!(
+            violations,
+            vec![Violation::MisplacedBefore {
+                chunk_type: "PLTE".to_string(),
+                before: "IDAT".to_string(),
+                index: 2,
+            }]
+        );
+        assert_eq!(violations[0].severity(), Severity::Error);
+    }
+
+    #[test]
+    fn test_ancillary_misplaced() {
+        let violations = check(&["IHDR", "tRNS", "PLTE", "IDAT", "gAMA", "IEND"]);
+        assert_eq!(
+            violations,
+            vec![
+                Violation::MisplacedAfter {
+                    chunk_type: "tRNS".to_string(),
+                    after: "PLTE".to_string(),
+                    index: 1,
+                },
+                Violation::MisplacedBefore {
+                    chunk_type: "gAMA".to_string(),
+                    before: "PLTE".to_string(),
+                    index: 4,
+                },
+                Violation::MisplacedBefore {
+                    chunk_type: "gAMA".to_string(),
+                    before: "IDAT".to_string(),
+                    index: 4,
+                },
+            ]
+        );
+        assert!(violations.iter().all(|v| v.severity() == Severity::Warning));
+    }
+
+    #[test]
+    fn test_iccp_with_srgb() {
+        let violations = check(&["IHDR", "sRGB", "iCCP", "IDAT", "IEND"]);
+        assert_eq!(violations, vec![Violation::IccpWithSrgb { index: 2 }]);
+    }
+
+    #[test]
+    fn test_unknown_chunks() {
+        let violations = check(&["IHDR", "RuSt", "IDAT", "ruSt", "rust", "IEND"]);
+        assert_eq!(
+            violations,
+            vec![
+                Violation::UnknownCritical { chunk_type: "RuSt".to_string(), index: 1 },
+                Violation::ReservedBit { chunk_type: "rust".to_string(), index: 4 },
+            ]
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use std::io::Write;
+
+use crate::chunk::Chunk;
+use crate::png::Png;
+use crate::{PngSecretError, Result};
+
+/// Write a png one chunk at a time into any [`Write`].
+///
+/// The signature is written by [`PngWriter::new`]. `IEND` must be the last
+/// chunk of a png, so any chunk written after it is refused.
+pub struct PngWriter<W: Write> {
+    writer: W,
+    iend_written: bool,
+}
+
+impl<W: Write> PngWriter<W> {
+    pub fn new(mut writer: W) -> Result<PngWriter<W>> {
+        writer.write_all(&Png::STANDARD_HEADER)?;
+
+        Ok(PngWriter {
+            writer,
+            iend_written: false,
+        })
+    }
+
+    pub fn write_chunk(&mut self, chunk: &Chunk) -> Result<()> {
+        if self.iend_written {
+            return Err(PngSecretError::ChunkAfterIend(chunk.chunk_type().clone()));
+        }
+
+        // write every part directly instead of building the chunk bytes
+        self.writer.write_all(&chunk.length().to_be_bytes())?;
+        self.writer.write_all(&chunk.chunk_type().bytes())?;
+        self.writer.write_all(chunk.data())?;
+        self.writer.write_all(&chunk.crc().to_be_bytes())?;
+
+        if chunk.chunk_type().to_string() == "IEND" {
+            self.iend_written = true;
+        }
+
+        Ok(())
+    }
+
+    /// `true` once the `IEND` chunk is written.
+    pub fn is_finished(&self) -> bool {
+        self.iend_written
+    }
+
+    /// Flush the output and give it back.
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn testing_chunks() -> Vec<Chunk> {
+        vec![
+            Chunk::new(ChunkType::from_str("FrSt").unwrap(), b"I am the first chunk".to_vec()),
+            Chunk::new(ChunkType::from_str("miDl").unwrap(), b"I am another chunk".to_vec()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+        ]
+    }
+
+    #[test]
+    fn test_write_chunks() {
+        let mut writer = PngWriter::new(Vec::new()).unwrap();
+        for chunk in testing_chunks() {
+            writer.write_chunk(&chunk).unwrap();
+        }
+        assert!(writer.is_finished());
+
+        let bytes = writer.finish().unwrap();
+        assert_eq!(bytes, Png::from_chunks(testing_chunks()).as_bytes());
+    }
+
+    #[test]
+    fn test_write_signature_only() {
+        let bytes = PngWriter::new(Vec::new()).unwrap().finish().unwrap();
+        assert_eq!(bytes, Png::STANDARD_HEADER);
+    }
+
+    #[test]
+    fn test_write_after_iend() {
+        let mut writer = PngWriter::new(Vec::new()).unwrap();
+        for chunk in testing_chunks() {
+            writer.write_chunk(&chunk).unwrap();
+        }
+
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"Too late".to_vec());
+        let result = writer.write_chunk(&chunk);
+        assert!(matches!(result, Err(PngSecretError::ChunkAfterIend(_))));
+
+        // nothing was written for the refused chunk
+        let bytes = writer.finish().unwrap();
+        assert_eq!(bytes, Png::from_chunks(testing_chunks()).as_bytes());
+    }
+}
@@ -0,0 +1,1012 @@
+//! zlib streams (RFC 1950) holding DEFLATE data (RFC 1951), the compression
+//! used by `IDAT`, `zTXt`, `iTXt` and `iCCP`.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io::{self, Read};
+
+use crate::{PngSecretError, Result};
+
+/// Decompression limit used for metadata chunks.
+pub const DEFAULT_LIMIT: usize = 64 * 1024 * 1024;
+
+// back references reach at most 32 KiB behind
+const WINDOW_SIZE: usize = 32 * 1024;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// order of the code length code lengths in a dynamic block header
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// Running Adler-32 checksum, the one ending a zlib stream.
+#[derive(Debug, Clone, Copy)]
+pub struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+impl Adler32 {
+    const MODULO: u32 = 65521;
+
+    pub fn new() -> Adler32 {
+        Adler32 { a: 1, b: 0 }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        // 5552 bytes is the most that can be summed before `b` overflows
+        for block in data.chunks(5552) {
+            for byte in block {
+                self.a += *byte as u32;
+                self.b += self.a;
+            }
+            self.a %= Adler32::MODULO;
+            self.b %= Adler32::MODULO;
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        (self.b << 16) | self.a
+    }
+}
+
+impl Default for Adler32 {
+    fn default() -> Self {
+        Adler32::new()
+    }
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    let mut adler = Adler32::new();
+    adler.update(data);
+    adler.finish()
+}
+
+/// Decompress a whole zlib stream, failing if the result is bigger than
+/// `limit` bytes.
+pub fn decompress(data: &[u8], limit: usize) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    ZlibDecoder::new(data, limit)
+        .read_to_end(&mut output)
+        .map_err(from_io_error)?;
+    Ok(output)
+}
+
+/// Compress `data` into a zlib stream.
+///
+/// `level` goes from 0, no compression, to 9, slowest and smallest.
+pub fn compress(data: &[u8], level: u8) -> Vec<u8> {
+    let level = level.min(9);
+
+    let cmf = 0x78; // deflate, 32 KiB window
+    let level_flag = match level {
+        0 | 1 => 0,
+        2..=5 => 1,
+        6 => 2,
+        _ => 3,
+    };
+    let mut flg = level_flag << 6;
+    flg += 31 - ((cmf as u16 * 256 + flg as u16) % 31) as u8;
+
+    let mut writer = BitWriter::new();
+    writer.output.extend([cmf, flg]);
+
+    if level == 0 {
+        write_stored_blocks(&mut writer, data, true);
+    } else {
+        deflate(&mut writer, data, level);
+    }
+
+    let mut output = writer.finish();
+    output.extend(adler32(data).to_be_bytes());
+    output
+}
+
+// get back the library error sent through `io::Error` by `ZlibDecoder`
+pub(crate) fn from_io_error(error: io::Error) -> PngSecretError {
+    if error.get_ref().is_some_and(|e| e.is::<PngSecretError>()) {
+        if let Ok(error) = error.into_inner().expect("inner error").downcast::<PngSecretError>() {
+            return *error;
+        }
+        unreachable!()
+    }
+    PngSecretError::Io(error)
+}
+
+fn invalid(reason: &str) -> PngSecretError {
+    PngSecretError::InvalidZlib(reason.to_string())
+}
+
+/// Streaming zlib decompression of everything read from `input`.
+///
+/// Reading fails once more than `limit` bytes come out, so untrusted data
+/// can't expand without bound. Errors come as `io::ErrorKind::InvalidData`
+/// wrapping a [`PngSecretError`].
+pub struct ZlibDecoder<R: Read> {
+    input: BitReader<R>,
+    state: State,
+    last_block: bool,
+    // the last 32 KiB already read, then the output not read yet
+    buffer: Vec<u8>,
+    position: usize,
+    total: usize,
+    limit: usize,
+    adler: Adler32,
+}
+
+enum State {
+    Header,
+    BlockHeader,
+    Stored(usize),
+    Huffman(Box<(Huffman, Huffman)>),
+    Checksum,
+    Done,
+}
+
+impl<R: Read> ZlibDecoder<R> {
+    pub fn new(input: R, limit: usize) -> ZlibDecoder<R> {
+        ZlibDecoder {
+            input: BitReader::new(input),
+            state: State::Header,
+            last_block: false,
+            buffer: Vec::new(),
+            position: 0,
+            total: 0,
+            limit,
+            adler: Adler32::new(),
+        }
+    }
+
+    /// Total number of decompressed bytes so far.
+    pub fn total_out(&self) -> usize {
+        self.total
+    }
+
+    // decompress about 32 KiB more
+    fn fill(&mut self) -> Result<()> {
+        if self.position > 2 * WINDOW_SIZE {
+            let consumed = self.position - WINDOW_SIZE;
+            self.buffer.drain(..consumed);
+            self.position -= consumed;
+        }
+
+        let start = self.buffer.len();
+        while self.buffer.len() - start < WINDOW_SIZE {
+            let next = match &self.state {
+                State::Header => {
+                    let cmf = self.input.bits(8)?;
+                    let flg = self.input.bits(8)?;
+                    if cmf & 0x0f != 8 || cmf >> 4 > 7 {
+                        return Err(invalid("unknown compression method"));
+                    }
+                    if (cmf << 8 | flg) % 31 != 0 {
+                        return Err(invalid("wrong header check"));
+                    }
+                    if flg & 0x20 != 0 {
+                        return Err(invalid("preset dictionaries aren't supported"));
+                    }
+                    State::BlockHeader
+                }
+                State::BlockHeader if self.last_block => State::Checksum,
+                State::BlockHeader => {
+                    self.last_block = self.input.bits(1)? == 1;
+                    match self.input.bits(2)? {
+                        0 => {
+                            self.input.align();
+                            let length = self.input.bits(16)?;
+                            let complement = self.input.bits(16)?;
+                            if length != !complement & 0xffff {
+                                return Err(invalid("stored block length doesn't match its complement"));
+                            }
+                            State::Stored(length as usize)
+                        }
+                        1 => State::Huffman(Box::new(fixed_tables())),
+                        2 => State::Huffman(Box::new(self.dynamic_tables()?)),
+                        _ => return Err(invalid("invalid block type")),
+                    }
+                }
+                State::Stored(0) => State::BlockHeader,
+                State::Stored(remaining) => {
+                    let length = (*remaining).min(WINDOW_SIZE);
+                    for _ in 0..length {
+                        let byte = self.input.byte()?;
+                        self.buffer.push(byte);
+                    }
+                    State::Stored(remaining - length)
+                }
+                State::Huffman(tables) => {
+                    let (literals, distances) = tables.as_ref();
+                    match decode_symbols(&mut self.input, &mut self.buffer, literals, distances, start)? {
+                        true => State::BlockHeader,
+                        false => continue,
+                    }
+                }
+                State::Checksum | State::Done => break,
+            };
+            self.state = next;
+        }
+
+        let produced = &self.buffer[start..];
+        self.total += produced.len();
+        if self.total > self.limit {
+            return Err(PngSecretError::DecompressionLimit(self.limit));
+        }
+        self.adler.update(produced);
+
+        if let State::Checksum = self.state {
+            self.input.align();
+            let checksum = (0..4).try_fold(0u32, |checksum, _| Ok::<_, PngSecretError>(checksum << 8 | self.input.byte()? as u32))?;
+            if checksum != self.adler.finish() {
+                return Err(invalid("Adler-32 checksum mismatch"));
+            }
+            self.state = State::Done;
+        }
+
+        Ok(())
+    }
+
+    fn dynamic_tables(&mut self) -> Result<(Huffman, Huffman)> {
+        let literal_count = self.input.bits(5)? as usize + 257;
+        let distance_count = self.input.bits(5)? as usize + 1;
+        let code_length_count = self.input.bits(4)? as usize + 4;
+        if literal_count > 286 || distance_count > 30 {
+            return Err(invalid("too many length or distance codes"));
+        }
+
+        let mut code_lengths = [0u8; 19];
+        for symbol in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+            code_lengths[*symbol] = self.input.bits(3)? as u8;
+        }
+        let code_lengths = Huffman::new(&code_lengths)?;
+
+        let mut lengths = Vec::with_capacity(literal_count + distance_count);
+        while lengths.len() < literal_count + distance_count {
+            let (length, repeat) = match code_lengths.decode(&mut self.input)? {
+                symbol @ 0..=15 => (symbol as u8, 1),
+                16 => {
+                    let previous = *lengths.last().ok_or_else(|| invalid("repeat with no previous length"))?;
+                    (previous, 3 + self.input.bits(2)?)
+                }
+                17 => (0, 3 + self.input.bits(3)?),
+                _ => (0, 11 + self.input.bits(7)?),
+            };
+            if lengths.len() + repeat as usize > literal_count + distance_count {
+                return Err(invalid("too many code lengths"));
+            }
+            lengths.extend(std::iter::repeat_n(length, repeat as usize));
+        }
+
+        if lengths[256] == 0 {
+            return Err(invalid("missing end of block code"));
+        }
+
+        Ok((Huffman::new(&lengths[..literal_count])?, Huffman::new(&lengths[literal_count..])?))
+    }
+}
+
+// decode until the end of the block, true, or 32 KiB of output, false
+fn decode_symbols<R: Read>(
+    input: &mut BitReader<R>,
+    buffer: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+    start: usize,
+) -> Result<bool> {
+    while buffer.len() - start < WINDOW_SIZE {
+        let symbol = literals.decode(input)? as usize;
+        if symbol < 256 {
+            buffer.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(true);
+        }
+
+        let index = symbol - 257;
+        if index >= LENGTH_BASE.len() {
+            return Err(invalid("invalid length code"));
+        }
+        let length = LENGTH_BASE[index] as usize + input.bits(LENGTH_EXTRA[index] as u32)? as usize;
+
+        let index = distances.decode(input)? as usize;
+        if index >= DISTANCE_BASE.len() {
+            return Err(invalid("invalid distance code"));
+        }
+        let distance = DISTANCE_BASE[index] as usize + input.bits(DISTANCE_EXTRA[index] as u32)? as usize;
+        if distance > buffer.len() {
+            return Err(invalid("distance too far back"));
+        }
+
+        // the source and the copy can overlap, copy byte by byte
+        let from = buffer.len() - distance;
+        for i in 0..length {
+            let byte = buffer[from + i];
+            buffer.push(byte);
+        }
+    }
+    Ok(false)
+}
+
+impl<R: Read> Read for ZlibDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.buffer.len() && !matches!(self.state, State::Done) {
+            self.fill().map_err(|e| match e {
+                PngSecretError::Io(e) => e,
+                e => io::Error::new(io::ErrorKind::InvalidData, e),
+            })?;
+        }
+
+        let available = &self.buffer[self.position..];
+        let length = available.len().min(buf.len());
+        buf[..length].copy_from_slice(&available[..length]);
+        self.position += length;
+        Ok(length)
+    }
+}
+
+// canonical Huffman code, decoded one bit at a time
+struct Huffman {
+    // number of codes of each length
+    counts: [u16; 16],
+    // symbols ordered by code
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman> {
+        let mut counts = [0u16; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+
+        // more codes than the lengths allow
+        let mut left: i32 = 1;
+        for count in &counts[1..] {
+            left = left * 2 - *count as i32;
+            if left < 0 {
+                return Err(invalid("over-subscribed Huffman code"));
+            }
+        }
+
+        let mut offsets = [0u16; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length != 0 {
+                symbols[offsets[*length as usize] as usize] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+
+        Ok(Huffman { counts, symbols })
+    }
+
+    fn decode<R: Read>(&self, input: &mut BitReader<R>) -> Result<u16> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for count in &self.counts[1..] {
+            code |= input.bits(1)? as i32;
+            let count = *count as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("invalid Huffman code"))
+    }
+}
+
+fn fixed_lengths() -> ([u8; 288], [u8; 30]) {
+    let mut literals = [8u8; 288];
+    literals[144..256].fill(9);
+    literals[256..280].fill(7);
+    (literals, [5u8; 30])
+}
+
+fn fixed_tables() -> (Huffman, Huffman) {
+    let (literals, distances) = fixed_lengths();
+    // the fixed codes are always valid
+    (Huffman::new(&literals).expect("fixed code"), Huffman::new(&distances).expect("fixed code"))
+}
+
+// reads bits least significant first, pulling bytes from `input` as needed
+struct BitReader<R: Read> {
+    input: R,
+    buffer: Vec<u8>,
+    start: usize,
+    end: usize,
+    bits: u32,
+    count: u32,
+}
+
+impl<R: Read> BitReader<R> {
+    fn new(input: R) -> BitReader<R> {
+        BitReader {
+            input,
+            buffer: vec![0; 4096],
+            start: 0,
+            end: 0,
+            bits: 0,
+            count: 0,
+        }
+    }
+
+    fn next_byte(&mut self) -> Result<u8> {
+        while self.start == self.end {
+            match self.input.read(&mut self.buffer) {
+                Ok(0) => return Err(invalid("unexpected end of data")),
+                Ok(length) => {
+                    self.start = 0;
+                    self.end = length;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        self.start += 1;
+        Ok(self.buffer[self.start - 1])
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32> {
+        while self.count < count {
+            self.bits |= (self.next_byte()? as u32) << self.count;
+            self.count += 8;
+        }
+        let value = self.bits & ((1 << count) - 1);
+        self.bits >>= count;
+        self.count -= count;
+        Ok(value)
+    }
+
+    // less than 8 bits are ever left, they are the rest of the current byte
+    fn align(&mut self) {
+        self.bits = 0;
+        self.count = 0;
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        self.bits(8).map(|byte| byte as u8)
+    }
+}
+
+// writes bits least significant first
+struct BitWriter {
+    output: Vec<u8>,
+    bits: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter {
+            output: Vec::new(),
+            bits: 0,
+            count: 0,
+        }
+    }
+
+    fn bits(&mut self, value: u32, count: u32) {
+        self.bits |= (value as u64) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.output.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes are written most significant bit first
+    fn code(&mut self, code: u16, length: u8) {
+        let reversed = code.reverse_bits() >> (16 - length as u32);
+        self.bits(reversed as u32, length as u32);
+    }
+
+    fn align(&mut self) {
+        if self.count > 0 {
+            self.bits(0, 8 - self.count);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.align();
+        self.output
+    }
+}
+
+fn write_stored_blocks(writer: &mut BitWriter, data: &[u8], last: bool) {
+    let mut blocks = data.chunks(65535).peekable();
+    if blocks.peek().is_none() {
+        writer.bits(last as u32, 3);
+        writer.align();
+        writer.output.extend([0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        writer.bits((last && blocks.peek().is_none()) as u32, 3);
+        writer.align();
+        let length = block.len() as u16;
+        writer.output.extend(length.to_le_bytes());
+        writer.output.extend((!length).to_le_bytes());
+        writer.output.extend(block);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Token {
+    Literal(u8),
+    Match { length: u16, distance: u16 },
+}
+
+fn length_code(length: u16) -> usize {
+    LENGTH_BASE.iter().rposition(|base| *base <= length).expect("length of at least 3")
+}
+
+fn distance_code(distance: u16) -> usize {
+    DISTANCE_BASE.iter().rposition(|base| *base <= distance).expect("distance of at least 1")
+}
+
+// LZ77 with hash chains, then one Huffman block every 64 Ki tokens
+fn deflate(writer: &mut BitWriter, data: &[u8], level: u8) {
+    const HASH_SIZE: usize = 1 << 15;
+    const BLOCK_TOKENS: usize = 1 << 16;
+    let max_chain = [0, 4, 8, 16, 32, 64, 128, 256, 1024, 4096][level as usize];
+
+    let hash = |i: usize| ((data[i] as usize) << 10 ^ (data[i + 1] as usize) << 5 ^ data[i + 2] as usize) % HASH_SIZE;
+    // most recent position of each hash, then the previous one with the same
+    // hash, only kept for the positions still in the window
+    let mut head = vec![usize::MAX; HASH_SIZE];
+    let mut previous = vec![usize::MAX; WINDOW_SIZE];
+    let insert = |i: usize, head: &mut [usize], previous: &mut [usize]| {
+        if i + 3 <= data.len() {
+            let h = hash(i);
+            previous[i % WINDOW_SIZE] = head[h];
+            head[h] = i;
+        }
+    };
+
+    let mut tokens = Vec::new();
+    let mut block_start = 0;
+    let mut i = 0;
+    while i < data.len() {
+        let mut best_length = 0;
+        let mut best_distance = 0;
+
+        if i + 3 <= data.len() {
+            let max_length = (data.len() - i).min(258);
+            let mut candidate = head[hash(i)];
+            let mut chain = 0;
+            while candidate != usize::MAX && i - candidate <= WINDOW_SIZE && chain < max_chain {
+                let length = data[candidate..]
+                    .iter()
+                    .zip(&data[i..i + max_length])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if length > best_length {
+                    best_length = length;
+                    best_distance = i - candidate;
+                    if length == max_length {
+                        break;
+                    }
+                }
+                candidate = previous[candidate % WINDOW_SIZE];
+                chain += 1;
+            }
+        }
+
+        if best_length >= 3 {
+            tokens.push(Token::Match {
+                length: best_length as u16,
+                distance: best_distance as u16,
+            });
+            for j in i..i + best_length {
+                insert(j, &mut head, &mut previous);
+            }
+            i += best_length;
+        } else {
+            tokens.push(Token::Literal(data[i]));
+            insert(i, &mut head, &mut previous);
+            i += 1;
+        }
+
+        if tokens.len() == BLOCK_TOKENS && i < data.len() {
+            write_block(writer, &tokens, &data[block_start..i], false);
+            tokens.clear();
+            block_start = i;
+        }
+    }
+
+    write_block(writer, &tokens, &data[block_start..], true);
+}
+
+// write the tokens as a fixed, dynamic or stored block, the smallest one
+fn write_block(writer: &mut BitWriter, tokens: &[Token], data: &[u8], last: bool) {
+    let mut literal_counts = [0u32; 286];
+    let mut distance_counts = [0u32; 30];
+    for token in tokens {
+        match token {
+            Token::Literal(byte) => literal_counts[*byte as usize] += 1,
+            Token::Match { length, distance } => {
+                literal_counts[257 + length_code(*length)] += 1;
+                distance_counts[distance_code(*distance)] += 1;
+            }
+        }
+    }
+    literal_counts[256] = 1;
+
+    let literal_lengths = huffman_lengths(&literal_counts, 15);
+    let distance_lengths = huffman_lengths(&distance_counts, 15);
+    let header = DynamicHeader::new(&literal_lengths, &distance_lengths);
+
+    let (fixed_literals, fixed_distances) = fixed_lengths();
+    let cost = |literal_lengths: &[u8], distance_lengths: &[u8]| -> usize {
+        let literals: usize = literal_counts.iter().zip(literal_lengths).map(|(c, l)| *c as usize * *l as usize).sum();
+        let distances: usize = distance_counts.iter().zip(distance_lengths).map(|(c, l)| *c as usize * *l as usize).sum();
+        let extra: usize = literal_counts[257..].iter().zip(LENGTH_EXTRA).map(|(c, e)| *c as usize * e as usize).sum::<usize>()
+            + distance_counts.iter().zip(DISTANCE_EXTRA).map(|(c, e)| *c as usize * e as usize).sum::<usize>();
+        literals + distances + extra
+    };
+    let dynamic_cost = header.cost() + cost(&literal_lengths, &distance_lengths);
+    let fixed_cost = cost(&fixed_literals, &fixed_distances);
+    let stored_cost = 8 * (data.len() + 5 * data.len().div_ceil(65535).max(1));
+
+    if stored_cost < dynamic_cost.min(fixed_cost) {
+        write_stored_blocks(writer, data, last);
+        return;
+    }
+
+    writer.bits(last as u32, 1);
+    if fixed_cost <= dynamic_cost {
+        writer.bits(1, 2);
+        write_tokens(writer, tokens, &fixed_literals, &fixed_distances);
+    } else {
+        writer.bits(2, 2);
+        header.write(writer);
+        write_tokens(writer, tokens, &literal_lengths, &distance_lengths);
+    }
+}
+
+fn write_tokens(writer: &mut BitWriter, tokens: &[Token], literal_lengths: &[u8], distance_lengths: &[u8]) {
+    let literal_codes = canonical_codes(literal_lengths);
+    let distance_codes = canonical_codes(distance_lengths);
+
+    for token in tokens {
+        match *token {
+            Token::Literal(byte) => writer.code(literal_codes[byte as usize], literal_lengths[byte as usize]),
+            Token::Match { length, distance } => {
+                let code = length_code(length);
+                writer.code(literal_codes[257 + code], literal_lengths[257 + code]);
+                writer.bits((length - LENGTH_BASE[code]) as u32, LENGTH_EXTRA[code] as u32);
+
+                let code = distance_code(distance);
+                writer.code(distance_codes[code], distance_lengths[code]);
+                writer.bits((distance - DISTANCE_BASE[code]) as u32, DISTANCE_EXTRA[code] as u32);
+            }
+        }
+    }
+    writer.code(literal_codes[256], literal_lengths[256]);
+}
+
+// code lengths of a dynamic block, themselves Huffman coded
+struct DynamicHeader {
+    literal_count: usize,
+    distance_count: usize,
+    // run length encoded code lengths, (symbol, extra bits)
+    symbols: Vec<(u8, u8)>,
+    // lengths of the code length code, by symbol
+    code_lengths: Vec<u8>,
+    // number of code length code lengths sent, in `CODE_LENGTH_ORDER`
+    code_length_count: usize,
+}
+
+impl DynamicHeader {
+    fn new(literal_lengths: &[u8], distance_lengths: &[u8]) -> DynamicHeader {
+        let literal_count = literal_lengths.iter().rposition(|l| *l != 0).map_or(0, |i| i + 1).max(257);
+        let distance_count = distance_lengths.iter().rposition(|l| *l != 0).map_or(0, |i| i + 1).max(1);
+        let lengths: Vec<u8> = literal_lengths[..literal_count]
+            .iter()
+            .chain(&distance_lengths[..distance_count])
+            .copied()
+            .collect();
+
+        let mut symbols = Vec::new();
+        let mut i = 0;
+        while i < lengths.len() {
+            let length = lengths[i];
+            let mut run = lengths[i..].iter().take_while(|l| **l == length).count();
+            i += run;
+
+            if length == 0 {
+                while run >= 11 {
+                    let repeat = run.min(138);
+                    symbols.push((18, (repeat - 11) as u8));
+                    run -= repeat;
+                }
+                if run >= 3 {
+                    symbols.push((17, (run - 3) as u8));
+                    run = 0;
+                }
+            } else {
+                symbols.push((length, 0));
+                run -= 1;
+                while run >= 3 {
+                    let repeat = run.min(6);
+                    symbols.push((16, (repeat - 3) as u8));
+                    run -= repeat;
+                }
+            }
+            symbols.extend(std::iter::repeat_n((length, 0), run));
+        }
+
+        let mut counts = [0u32; 19];
+        for (symbol, _) in &symbols {
+            counts[*symbol as usize] += 1;
+        }
+        let code_lengths = huffman_lengths(&counts, 7);
+        let code_length_count = CODE_LENGTH_ORDER
+            .iter()
+            .rposition(|s| code_lengths[*s] != 0)
+            .map_or(0, |i| i + 1)
+            .max(4);
+
+        DynamicHeader {
+            literal_count,
+            distance_count,
+            symbols,
+            code_lengths,
+            code_length_count,
+        }
+    }
+
+    fn extra_bits(symbol: u8) -> u32 {
+        match symbol {
+            16 => 2,
+            17 => 3,
+            18 => 7,
+            _ => 0,
+        }
+    }
+
+    // size in bits
+    fn cost(&self) -> usize {
+        let symbols: usize = self.symbols
+            .iter()
+            .map(|(symbol, _)| self.code_lengths[*symbol as usize] as usize + DynamicHeader::extra_bits(*symbol) as usize)
+            .sum();
+        14 + 3 * self.code_length_count + symbols
+    }
+
+    fn write(&self, writer: &mut BitWriter) {
+        writer.bits((self.literal_count - 257) as u32, 5);
+        writer.bits((self.distance_count - 1) as u32, 5);
+        writer.bits((self.code_length_count - 4) as u32, 4);
+        for symbol in CODE_LENGTH_ORDER.iter().take(self.code_length_count) {
+            writer.bits(self.code_lengths[*symbol] as u32, 3);
+        }
+
+        let codes = canonical_codes(&self.code_lengths);
+        for (symbol, extra) in &self.symbols {
+            writer.code(codes[*symbol as usize], self.code_lengths[*symbol as usize]);
+            writer.bits(*extra as u32, DynamicHeader::extra_bits(*symbol));
+        }
+    }
+}
+
+// Huffman code lengths for these symbol counts, no longer than `max_length`
+fn huffman_lengths(counts: &[u32], max_length: u8) -> Vec<u8> {
+    let mut counts = counts.to_vec();
+    loop {
+        let lengths = huffman_tree_depths(&counts);
+        if lengths.iter().all(|l| *l <= max_length) {
+            return lengths;
+        }
+        // flatten the counts until the tree is shallow enough
+        for count in counts.iter_mut().filter(|c| **c > 0) {
+            *count = count.div_ceil(2);
+        }
+    }
+}
+
+fn huffman_tree_depths(counts: &[u32]) -> Vec<u8> {
+    let mut lengths = vec![0u8; counts.len()];
+    let used: Vec<usize> = (0..counts.len()).filter(|s| counts[*s] > 0).collect();
+    match used.len() {
+        0 => return lengths,
+        // a lone code must still be complete for some decoders
+        1 => {
+            lengths[used[0]] = 1;
+            lengths[if used[0] == 0 { 1 } else { 0 }] = 1;
+            return lengths;
+        }
+        _ => {}
+    }
+
+    // nodes are the symbols, then the internal nodes with their children
+    let mut children: Vec<(usize, usize)> = Vec::new();
+    let mut heap: BinaryHeap<Reverse<(u64, usize)>> =
+        used.iter().map(|s| Reverse((counts[*s] as u64, *s))).collect();
+    while heap.len() > 1 {
+        let Reverse((count_a, a)) = heap.pop().expect("two nodes");
+        let Reverse((count_b, b)) = heap.pop().expect("two nodes");
+        children.push((a, b));
+        heap.push(Reverse((count_a + count_b, counts.len() + children.len() - 1)));
+    }
+
+    let Reverse((_, root)) = heap.pop().expect("root node");
+    let mut stack = vec![(root, 0u8)];
+    while let Some((node, depth)) = stack.pop() {
+        if node < counts.len() {
+            lengths[node] = depth;
+        } else {
+            let (a, b) = children[node - counts.len()];
+            stack.push((a, depth + 1));
+            stack.push((b, depth + 1));
+        }
+    }
+    lengths
+}
+
+fn canonical_codes(lengths: &[u8]) -> Vec<u16> {
+    let mut counts = [0u16; 16];
+    for length in lengths {
+        counts[*length as usize] += 1;
+    }
+    counts[0] = 0;
+
+    let mut next_code = [0u16; 16];
+    let mut code = 0;
+    for length in 1..16 {
+        code = (code + counts[length - 1]) << 1;
+        next_code[length] = code;
+    }
+
+    lengths
+        .iter()
+        .map(|length| {
+            if *length == 0 {
+                return 0;
+            }
+            let code = next_code[*length as usize];
+            next_code[*length as usize] += 1;
+            code
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // "Hello, world!" compressed by zlib
+    const HELLO: [u8; 21] = [
+        0x78, 0x9c, 0xf3, 0x48, 0xcd, 0xc9, 0xc9, 0xd7, 0x51, 0x28, 0xcf, 0x2f, 0xca, 0x49, 0x51,
+        0x04, 0x00, 0x20, 0x5e, 0x04, 0x8a,
+    ];
+
+    fn sample_data() -> Vec<u8> {
+        let mut data = Vec::new();
+        for i in 0..20_000u32 {
+            data.extend(format!("line {} of {}\n", i % 97, i % 13).as_bytes());
+            data.push((i.wrapping_mul(2654435761) >> 24) as u8);
+        }
+        data
+    }
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+
+        let data = vec![0xffu8; 100_000];
+        let mut adler = Adler32::new();
+        adler.update(&data[..3]);
+        adler.update(&data[3..]);
+        assert_eq!(adler.finish(), adler32(&data));
+    }
+
+    #[test]
+    fn test_decompress_fixed() {
+        assert_eq!(decompress(&HELLO, 100).unwrap(), b"Hello, world!");
+    }
+
+    #[test]
+    fn test_decompress_stored() {
+        let compressed = [0x78, 0x01, 0x01, 0x03, 0x00, 0xfc, 0xff, b'a', b'b', b'c', 0x02, 0x4d, 0x01, 0x27];
+        assert_eq!(decompress(&compressed, 100).unwrap(), b"abc");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = sample_data();
+        for level in [0, 1, 6, 9] {
+            let compressed = compress(&data, level);
+            assert_eq!(decompress(&compressed, data.len()).unwrap(), data);
+        }
+        assert!(compress(&data, 6).len() < data.len() / 3);
+    }
+
+    #[test]
+    fn test_round_trip_edge_cases() {
+        for data in [vec![], vec![7], vec![0; 200_000], (0..=255).collect::<Vec<u8>>()] {
+            for level in [0, 6] {
+                assert_eq!(decompress(&compress(&data, level), usize::MAX).unwrap(), data);
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip_window() {
+        // noise repeated right at the edge of the window, then past it
+        let mut state = 1u32;
+        let noise: Vec<u8> = (0..WINDOW_SIZE)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u8
+            })
+            .collect();
+        let data = [noise.as_slice(), &noise, &noise[..1000], &[0; 10_000], &noise].concat();
+        for level in [1, 9] {
+            let compressed = compress(&data, level);
+            assert_eq!(decompress(&compressed, data.len()).unwrap(), data);
+            assert!(compressed.len() < 2 * WINDOW_SIZE);
+        }
+    }
+
+    #[test]
+    fn test_limit() {
+        let compressed = compress(&[0; 100_000], 6);
+        assert!(matches!(decompress(&compressed, 99_999), Err(PngSecretError::DecompressionLimit(99_999))));
+        assert!(decompress(&compressed, 100_000).is_ok());
+    }
+
+    #[test]
+    fn test_streaming() {
+        let data = sample_data();
+        let compressed = compress(&data, 6);
+
+        let mut decoder = ZlibDecoder::new(compressed.as_slice(), usize::MAX);
+        let mut output: Vec<u8> = Vec::new();
+        let mut buffer = [0; 1000];
+        loop {
+            let length = decoder.read(&mut buffer).unwrap();
+            if length == 0 {
+                break;
+            }
+            output.extend(&buffer[..length]);
+        }
+        assert_eq!(output, data);
+        assert_eq!(decoder.total_out(), data.len());
+    }
+
+    #[test]
+    fn test_bad_checksum() {
+        let mut compressed = HELLO;
+        compressed[20] ^= 1;
+        assert!(matches!(decompress(&compressed, 100), Err(PngSecretError::InvalidZlib(_))));
+    }
+
+    #[test]
+    fn test_invalid_streams() {
+        assert!(decompress(&[], 100).is_err());
+        assert!(decompress(&[0x78, 0x9d], 100).is_err());
+        assert!(decompress(&[0x79, 0x9c], 100).is_err());
+        assert!(decompress(&HELLO[..10], 100).is_err());
+        // block type 3
+        assert!(decompress(&[0x78, 0x9c, 0x07], 100).is_err());
+    }
+}
@@ -0,0 +1,43 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::str::FromStr;
+
+use png_secret::{Chunk, ChunkType, ColorType, Ihdr, Image, InterlaceMethod, Png};
+
+fn png_secret(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_png-secret")).args(args).output().unwrap()
+}
+
+// a small truecolor png written to the temp directory
+fn temp_png(name: &str) -> PathBuf {
+    let ihdr = Ihdr::new(40, 40, 8, ColorType::Truecolor, InterlaceMethod::None).unwrap();
+    let image = Image::new(ihdr, (0..40 * 40 * 3).map(|i| (i * 31) as u8).collect()).unwrap();
+    let mut png = Png::from_chunks(vec![ihdr.into(), Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![])]);
+    png.set_image(&image);
+
+    let path = std::env::temp_dir().join(format!("png-secret-cli-{}-{}.png", name, std::process::id()));
+    fs::write(&path, png.as_bytes()).unwrap();
+    path
+}
+
+#[test]
+fn test_wrong_lsb_key() {
+    let path = temp_png("lsb-key");
+    let file = path.to_str().unwrap();
+
+    let output = png_secret(&["encode", file, "RuSt", "Pixel secret", "--method", "lsb", "--lsb-key", "right"]);
+    assert!(output.status.success());
+
+    let output = png_secret(&["decode", file, "RuSt", "--method", "lsb", "--lsb-key", "wrong"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("error: No message found with this lsb key"), "{}", stderr);
+    assert!(!stderr.contains("WrongLsbKey"));
+
+    let output = png_secret(&["decode", file, "RuSt", "--method", "lsb", "--lsb-key", "right"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout).unwrap().contains("Pixel secret"));
+
+    fs::remove_file(path).unwrap();
+}